@@ -1,171 +1,261 @@
 aoc::parts!(1, 2);
 
 use regex::Regex;
-use std::collections::HashSet;
-
-fn is_valid_passport(passport: &str) -> bool {
-    let required_fields: HashSet<&str> = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"]
-        .iter()
-        .cloned()
-        .collect();
-    let mut present_fields = HashSet::new();
-
-    for field in passport.split_whitespace() {
-        let key_value: Vec<&str> = field.split(':').collect();
-        if key_value.len() == 2 {
-            present_fields.insert(key_value[0]);
+use std::fmt;
+use std::ops::RangeInclusive;
+
+/// What a field's value has to look like.
+enum Check {
+    /// Anything goes.
+    Any,
+    /// A number in the range.
+    Range(RangeInclusive<u32>),
+    /// A number followed by one of the units, in that unit's range.
+    Measure(Vec<(&'static str, RangeInclusive<u32>)>),
+    /// The whole value matches the pattern.
+    Pattern(Regex),
+    /// One of the listed values.
+    OneOf(&'static [&'static str]),
+}
+
+struct Field {
+    key: &'static str,
+    required: bool,
+    check: Check,
+}
+
+/// The fields a passport may have, with their checks compiled up front.
+struct Schema {
+    fields: Vec<Field>,
+    /// Whether fields outside the schema and entries without a `key:value` shape are failures.
+    strict: bool,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Reason {
+    Missing,
+    /// The entry is not of the form `key:value`.
+    Malformed,
+    /// The schema has no such field.
+    Unknown,
+    NotANumber,
+    OutOfRange(RangeInclusive<u32>),
+    UnknownUnit,
+    NoMatch(String),
+    NotOneOf(&'static [&'static str]),
+}
+
+/// A field that failed validation.
+#[derive(Debug, PartialEq, Eq)]
+struct Failure {
+    field: String,
+    reason: Reason,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.field)?;
+        match &self.reason {
+            Reason::Missing => write!(f, "missing"),
+            Reason::Malformed => write!(f, "not a key:value pair"),
+            Reason::Unknown => write!(f, "unknown field"),
+            Reason::NotANumber => write!(f, "not a number"),
+            Reason::OutOfRange(range) => {
+                write!(f, "outside {}..={}", range.start(), range.end())
+            }
+            Reason::UnknownUnit => write!(f, "missing or unknown unit"),
+            Reason::NoMatch(pattern) => write!(f, "does not match {pattern}"),
+            Reason::NotOneOf(values) => write!(f, "not one of {}", values.join(", ")),
         }
     }
+}
+
+impl Check {
+    fn apply(&self, value: &str) -> Result<(), Reason> {
+        match self {
+            Check::Any => Ok(()),
+            Check::Range(range) => check_range(value, range),
+            Check::Measure(units) => {
+                let digits =
+                    value.len() - value.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+                let (number, unit) = value.split_at(digits);
+                let (_, range) = units
+                    .iter()
+                    .find(|(u, _)| *u == unit)
+                    .ok_or(Reason::UnknownUnit)?;
+                check_range(number, range)
+            }
+            Check::Pattern(regex) => regex
+                .is_match(value)
+                .then_some(())
+                .ok_or_else(|| Reason::NoMatch(regex.as_str().to_string())),
+            Check::OneOf(values) => values
+                .contains(&value)
+                .then_some(())
+                .ok_or(Reason::NotOneOf(values)),
+        }
+    }
+}
 
-    required_fields.is_subset(&present_fields)
+fn check_range(value: &str, range: &RangeInclusive<u32>) -> Result<(), Reason> {
+    let number: u32 = value.parse().map_err(|_| Reason::NotANumber)?;
+    range
+        .contains(&number)
+        .then_some(())
+        .ok_or_else(|| Reason::OutOfRange(range.clone()))
 }
 
-fn is_valid_passport_part_2(passport: &str) -> bool {
-    if !is_valid_passport(passport) {
-        return false;
+/// Required fields for both parts; `cid` is optional.
+const REQUIRED_FIELDS: [&str; 7] = ["byr", "iyr", "eyr", "hgt", "hcl", "ecl", "pid"];
+
+impl Schema {
+    /// Every required field is present, whatever its value.
+    fn part_1() -> Schema {
+        let required = REQUIRED_FIELDS.iter().map(|&key| Field {
+            key,
+            required: true,
+            check: Check::Any,
+        });
+        Schema {
+            fields: required.collect(),
+            strict: false,
+        }
     }
 
-    for field in passport.split_whitespace() {
-        let key_value: Vec<&str> = field.split(':').collect();
-        if key_value.len() != 2 {
-            return false;
+    /// Every required field is present and valid, and nothing else is there apart from `cid`.
+    fn part_2() -> Schema {
+        let field = |key, check| Field {
+            key,
+            required: true,
+            check,
+        };
+        Schema {
+            fields: vec![
+                field("byr", Check::Range(1920..=2002)),
+                field("iyr", Check::Range(2010..=2020)),
+                field("eyr", Check::Range(2020..=2030)),
+                field(
+                    "hgt",
+                    Check::Measure(vec![("cm", 150..=193), ("in", 59..=76)]),
+                ),
+                field(
+                    "hcl",
+                    Check::Pattern(Regex::new(r"^#[0-9a-f]{6}$").unwrap()),
+                ),
+                field(
+                    "ecl",
+                    Check::OneOf(&["amb", "blu", "brn", "gry", "grn", "hzl", "oth"]),
+                ),
+                field("pid", Check::Pattern(Regex::new(r"^\d{9}$").unwrap())),
+                Field {
+                    key: "cid",
+                    required: false,
+                    check: Check::Any,
+                },
+            ],
+            strict: true,
         }
-        let key = key_value[0];
-        let value = key_value[1];
-
-        match key {
-            "byr" => {
-                if let Ok(year) = value.parse::<u32>() {
-                    if !(1920..=2002).contains(&year) {
-                        return false;
-                    }
-                } else {
-                    return false;
-                }
-            }
-            "iyr" => {
-                if let Ok(year) = value.parse::<u32>() {
-                    if !(2010..=2020).contains(&year) {
-                        return false;
-                    }
-                } else {
-                    return false;
-                }
-            }
-            "eyr" => {
-                if let Ok(year) = value.parse::<u32>() {
-                    if !(2020..=2030).contains(&year) {
-                        return false;
-                    }
-                } else {
-                    return false;
-                }
-            }
-            "hgt" => {
-                if let Some(captures) = Regex::new(r"^(\d+)(cm|in)$").unwrap().captures(value) {
-                    if let Ok(height) = captures[1].parse::<u32>() {
-                        match &captures[2] {
-                            "cm" => {
-                                if !(150..=193).contains(&height) {
-                                    return false;
-                                }
-                            }
-                            "in" => {
-                                if !(59..=76).contains(&height) {
-                                    return false;
-                                }
-                            }
-                            _ => return false,
-                        }
-                    } else {
-                        return false;
-                    }
-                } else {
-                    return false;
-                }
-            }
-            "hcl" => {
-                if !Regex::new(r"^#[0-9a-f]{6}$").unwrap().is_match(value) {
-                    return false;
-                }
+    }
+
+    /// Every failing field of the passport, in schema order followed by entries it does not know.
+    fn validate(&self, passport: &str) -> Vec<Failure> {
+        let mut entries = Vec::new();
+        let mut malformed = Vec::new();
+        for entry in passport.split_whitespace() {
+            match entry.split_once(':') {
+                Some((key, value)) if !value.contains(':') => entries.push((key, value)),
+                _ if self.strict => malformed.push(Failure {
+                    field: entry.to_string(),
+                    reason: Reason::Malformed,
+                }),
+                _ => {}
             }
-            "ecl" => {
-                if !["amb", "blu", "brn", "gry", "grn", "hzl", "oth"].contains(&value) {
-                    return false;
-                }
+        }
+
+        let mut report = Vec::new();
+        for field in &self.fields {
+            let values: Vec<_> = entries
+                .iter()
+                .filter(|(key, _)| *key == field.key)
+                .collect();
+            if values.is_empty() && field.required {
+                report.push(Failure {
+                    field: field.key.to_string(),
+                    reason: Reason::Missing,
+                });
             }
-            "pid" => {
-                if !Regex::new(r"^\d{9}$").unwrap().is_match(value) {
-                    return false;
+            for (_, value) in values {
+                if let Err(reason) = field.check.apply(value) {
+                    report.push(Failure {
+                        field: field.key.to_string(),
+                        reason,
+                    });
                 }
             }
-            "cid" => {}
-            _ => return false,
         }
+
+        if self.strict {
+            let unknown = entries
+                .iter()
+                .filter(|(key, _)| self.fields.iter().all(|field| field.key != *key));
+            report.extend(unknown.map(|(key, _)| Failure {
+                field: key.to_string(),
+                reason: Reason::Unknown,
+            }));
+        }
+        report.extend(malformed);
+        report
     }
 
-    true
+    fn count_valid(&self, input: &str) -> usize {
+        input
+            .split("\n\n")
+            .filter(|passport| self.validate(passport).is_empty())
+            .count()
+    }
 }
 
 fn part_1(input: aoc::Input) -> impl ToString {
-    let valid_passports = input
-        .raw()
-        .split("\n\n")
-        .filter(|passport| is_valid_passport(passport))
-        .count();
-
-    valid_passports
+    Schema::part_1().count_valid(input.raw())
 }
 
 fn part_2(input: aoc::Input) -> impl ToString {
-    let valid_passports = input
-        .raw()
-        .split("\n\n")
-        .filter(|passport| is_valid_passport_part_2(passport))
-        .count();
-
-    valid_passports
+    Schema::part_2().count_valid(input.raw())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    // ... (Existing tests for part_1) ...
     #[test]
     fn test_valid_passport() {
         let passport =
             "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd byr:1937 iyr:2017 cid:147 hgt:183cm";
-        assert!(is_valid_passport(passport));
+        assert!(Schema::part_1().validate(passport).is_empty());
     }
 
     #[test]
     fn test_invalid_passport_missing_hgt() {
         let passport = "iyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884 hcl:#cfa07d byr:1929";
-        assert!(!is_valid_passport(passport));
+        assert!(!Schema::part_1().validate(passport).is_empty());
     }
 
     #[test]
     fn test_valid_passport_missing_cid() {
         let passport = "hcl:#ae17e1 iyr:2013 eyr:2024 ecl:brn pid:760753108 byr:1931 hgt:179cm";
-        assert!(is_valid_passport(passport));
+        assert!(Schema::part_1().validate(passport).is_empty());
     }
 
     #[test]
     fn test_invalid_passport_missing_byr_and_cid() {
         let passport = "hcl:#cfa07d eyr:2025 pid:166559648 iyr:2011 ecl:brn hgt:59in";
-        assert!(!is_valid_passport(passport));
+        assert!(!Schema::part_1().validate(passport).is_empty());
     }
 
     #[test]
     fn test_part_1_example() {
         let input = "ecl:gry pid:860033327 eyr:2020 hcl:#fffffd\nbyr:1937 iyr:2017 cid:147 hgt:183cm\n\niyr:2013 ecl:amb cid:350 eyr:2023 pid:028048884\nhcl:#cfa07d byr:1929\n\nhcl:#ae17e1 iyr:2013\neyr:2024\necl:brn pid:760753108 byr:1931\nhgt:179cm\n\nhcl:#cfa07d eyr:2025 pid:166559648\niyr:2011 ecl:brn hgt:59in";
-        let passports: Vec<&str> = input.split("\n\n").collect();
-        let valid_count: usize = passports
-            .iter()
-            .filter(|p| is_valid_passport(p))
-            .map(|_| 1)
-            .sum();
+        let valid_count = Schema::part_1().count_valid(input);
         assert_eq!(valid_count, 2);
     }
 
@@ -177,8 +267,9 @@ mod tests {
             "hcl:dab227 iyr:2012\necl:brn hgt:182cm pid:021572410 eyr:2020 byr:1992 cid:277",
             "hgt:59cm ecl:zzz\neyr:2038 hcl:74454a iyr:2023\npid:3556412378 byr:2007",
         ];
+        let schema = Schema::part_2();
         for input in inputs.iter() {
-            assert!(!is_valid_passport_part_2(input));
+            assert!(!schema.validate(input).is_empty());
         }
     }
 
@@ -190,20 +281,37 @@ mod tests {
             "hcl:#888785\nhgt:164cm byr:2001 iyr:2015 cid:88\npid:545766238 ecl:hzl\neyr:2022",
             "iyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719",
         ];
+        let schema = Schema::part_2();
         for input in inputs.iter() {
-            assert!(is_valid_passport_part_2(input));
+            assert_eq!(schema.validate(input), vec![]);
         }
     }
 
     #[test]
     fn test_part_2_example() {
         let input = "pid:087499704 hgt:74in ecl:grn iyr:2012 eyr:2030 byr:1980\nhcl:#623a2f\n\neyr:2029 ecl:blu cid:129 byr:1989\niyr:2014 pid:896056539 hcl:#a97842 hgt:165cm\n\nhcl:#888785\nhgt:164cm byr:2001 iyr:2015 cid:88\npid:545766238 ecl:hzl\neyr:2022\n\niyr:2010 hgt:158cm hcl:#b6652a ecl:blu byr:1944 eyr:2021 pid:093154719";
-        let passports: Vec<&str> = input.split("\n\n").collect();
-        let valid_count: usize = passports
-            .iter()
-            .filter(|p| is_valid_passport_part_2(p))
-            .map(|_| 1)
-            .sum();
+        let valid_count = Schema::part_2().count_valid(input);
         assert_eq!(valid_count, 4);
     }
+
+    #[test]
+    fn test_failure_report() {
+        let passport = "eyr:1972 cid:100\nhcl:#18171d ecl:amb hgt:170 pid:186cm iyr:2018 foo:1 bar";
+        let report: Vec<_> = Schema::part_2()
+            .validate(passport)
+            .iter()
+            .map(|failure| failure.to_string())
+            .collect();
+        assert_eq!(
+            report,
+            [
+                "byr: missing",
+                "eyr: outside 2020..=2030",
+                "hgt: missing or unknown unit",
+                r"pid: does not match ^\d{9}$",
+                "foo: unknown field",
+                "bar: not a key:value pair",
+            ]
+        );
+    }
 }
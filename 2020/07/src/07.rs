@@ -3,188 +3,254 @@ aoc::parts!(1, 2);
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 
-fn can_contain_shiny_gold(
-    rules: &HashMap<String, Vec<(usize, String)>>,
-    color: &str,
-    cache: &mut HashMap<String, bool>,
-) -> bool {
-    if let Some(&result) = cache.get(color) {
-        return result;
-    }
+/// The colour every puzzle question is about.
+const SHINY_GOLD: &str = "shiny gold";
+
+/// Bag rules with every colour interned to an index into `names`.
+struct BagGraph {
+    names: Vec<String>,
+    ids: HashMap<String, usize>,
+    /// `(count, bag)` pairs each bag must directly contain.
+    contents: Vec<Vec<(u64, usize)>>,
+}
 
-    if color == "shiny gold" {
-        return true;
-    }
+#[derive(Debug, PartialEq, Eq)]
+enum BagError {
+    /// No rule mentions this colour.
+    UnknownColour(String),
+    /// These bags must each contain the next, and the last the first, so none can be packed.
+    Cycle(Vec<String>),
+}
 
-    if let Some(contents) = rules.get(color) {
-        let result = contents
-            .iter()
-            .any(|(_, contained_color)| can_contain_shiny_gold(rules, contained_color, cache));
-        cache.insert(color.to_string(), result);
-        return result;
-    }
+impl BagGraph {
+    fn parse(input: &str) -> BagGraph {
+        let bag_rules_regex = Regex::new(r"(\w+ \w+) bags contain (.*)").unwrap();
+        let contained_bag_regex = Regex::new(r"(\d+) (\w+ \w+) bags?").unwrap();
+        let mut graph = BagGraph {
+            names: Vec::new(),
+            ids: HashMap::new(),
+            contents: Vec::new(),
+        };
 
-    false
-}
+        for line in input.lines() {
+            let captures = bag_rules_regex.captures(line).unwrap();
+            let bag = graph.intern(&captures[1]);
+            for contained_bag_capture in contained_bag_regex.captures_iter(&captures[2]) {
+                let count = contained_bag_capture[1].parse().unwrap();
+                let contained = graph.intern(&contained_bag_capture[2]);
+                graph.contents[bag].push((count, contained));
+            }
+        }
 
-fn count_contained_bags(rules: &HashMap<String, Vec<(usize, String)>>, color: &str) -> usize {
-    let mut total_bags = 0;
+        graph
+    }
 
-    if let Some(contents) = rules.get(color) {
-        for (count, contained_color) in contents {
-            total_bags += count; // Add the direct count of contained bags
-            total_bags += count * count_contained_bags(rules, contained_color); // Add bags inside those
+    fn intern(&mut self, colour: &str) -> usize {
+        if let Some(&id) = self.ids.get(colour) {
+            return id;
         }
+        let id = self.names.len();
+        self.names.push(colour.to_string());
+        self.ids.insert(colour.to_string(), id);
+        self.contents.push(Vec::new());
+        id
     }
 
-    total_bags
-}
+    fn id(&self, colour: &str) -> Result<usize, BagError> {
+        self.ids
+            .get(colour)
+            .copied()
+            .ok_or_else(|| BagError::UnknownColour(colour.to_string()))
+    }
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let bag_rules_regex = Regex::new(r"(\w+ \w+) bags contain (.*)").unwrap();
-    let contained_bag_regex = Regex::new(r"(\d+) (\w+ \w+) bags?").unwrap();
-    let mut rules = HashMap::new();
-
-    for line in input.raw().lines() {
-        let captures = bag_rules_regex.captures(line).unwrap();
-        let bag_color = captures[1].to_string();
-        let contents_str = &captures[2];
-
-        let mut contents = Vec::new();
-        if contents_str != "no other bags." {
-            for contained_bag_capture in contained_bag_regex.captures_iter(contents_str) {
-                let count = contained_bag_capture[1].parse().unwrap();
-                let contained_color = contained_bag_capture[2].to_string();
-                contents.push((count, contained_color));
+    /// Colours of every bag that can eventually contain a `colour` bag, in sorted order.
+    fn containers(&self, colour: &str) -> Result<Vec<&str>, BagError> {
+        let target = self.id(colour)?;
+        let mut parents = vec![Vec::new(); self.names.len()];
+        for (bag, contents) in self.contents.iter().enumerate() {
+            for &(_, contained) in contents {
+                parents[contained].push(bag);
             }
         }
 
-        rules.insert(bag_color, contents);
+        let mut found = HashSet::new();
+        let mut stack = vec![target];
+        while let Some(bag) = stack.pop() {
+            for &parent in &parents[bag] {
+                if found.insert(parent) {
+                    stack.push(parent);
+                }
+            }
+        }
+        // A bag only contains itself through an impossible cycle.
+        found.remove(&target);
+
+        let mut colours: Vec<_> = found
+            .into_iter()
+            .map(|bag| self.names[bag].as_str())
+            .collect();
+        colours.sort_unstable();
+        Ok(colours)
     }
 
-    let mut cache = HashMap::new();
-    let count = rules
-        .keys()
-        .filter(|&color| color != "shiny gold" && can_contain_shiny_gold(&rules, color, &mut cache))
-        .count();
+    /// Computes a value for `bag` from the values of the bags it contains, visiting each bag once.
+    ///
+    /// `path` holds the bags currently being evaluated, so meeting one of them again is a cycle.
+    fn evaluate<T: Clone>(
+        &self,
+        bag: usize,
+        combine: &impl Fn(&[(u64, usize)], &[T]) -> T,
+        memo: &mut HashMap<usize, T>,
+        path: &mut Vec<usize>,
+    ) -> Result<T, BagError> {
+        if let Some(value) = memo.get(&bag) {
+            return Ok(value.clone());
+        }
+        if let Some(start) = path.iter().position(|&b| b == bag) {
+            let cycle = path[start..].iter().map(|&b| self.names[b].clone());
+            return Err(BagError::Cycle(cycle.collect()));
+        }
 
-    count
-}
+        path.push(bag);
+        let values = self.contents[bag]
+            .iter()
+            .map(|&(_, contained)| self.evaluate(contained, combine, memo, path))
+            .collect::<Result<Vec<_>, _>>()?;
+        path.pop();
 
-fn part_2(input: aoc::Input) -> impl ToString {
-    let bag_rules_regex = Regex::new(r"(\w+ \w+) bags contain (.*)").unwrap();
-    let contained_bag_regex = Regex::new(r"(\d+) (\w+ \w+) bags?").unwrap();
-    let mut rules = HashMap::new();
-
-    for line in input.raw().lines() {
-        let captures = bag_rules_regex.captures(line).unwrap();
-        let bag_color = captures[1].to_string();
-        let contents_str = &captures[2];
-
-        let mut contents = Vec::new();
-        if contents_str != "no other bags." {
-            for contained_bag_capture in contained_bag_regex.captures_iter(contents_str) {
-                let count = contained_bag_capture[1].parse().unwrap();
-                let contained_color = contained_bag_capture[2].to_string();
-                contents.push((count, contained_color));
-            }
-        }
+        let value = combine(&self.contents[bag], &values);
+        memo.insert(bag, value.clone());
+        Ok(value)
+    }
 
-        rules.insert(bag_color, contents);
+    /// Total number of bags inside a `colour` bag.
+    fn bags_inside(&self, colour: &str) -> Result<u64, BagError> {
+        let count = |contents: &[(u64, usize)], inside: &[u64]| {
+            contents
+                .iter()
+                .zip(inside)
+                .map(|(&(count, _), inside)| count * (1 + inside))
+                .sum()
+        };
+        self.evaluate(
+            self.id(colour)?,
+            &count,
+            &mut HashMap::new(),
+            &mut Vec::new(),
+        )
+    }
+
+    /// Longest chain of bags nested one inside the next, starting with `colour`.
+    #[allow(dead_code)] // Only exercised by the tests.
+    fn deepest_chain(&self, colour: &str) -> Result<Vec<&str>, BagError> {
+        let chain = |contents: &[(u64, usize)], chains: &[Vec<usize>]| {
+            // Reversed so that ties go to the bag listed first in the rule.
+            let deepest = chains.iter().zip(contents).rev();
+            let deepest = deepest.max_by_key(|(chain, _)| chain.len());
+            deepest.map_or_else(Vec::new, |(chain, &(_, contained))| {
+                std::iter::once(contained).chain(chain.clone()).collect()
+            })
+        };
+        let id = self.id(colour)?;
+        let nested = self.evaluate(id, &chain, &mut HashMap::new(), &mut Vec::new())?;
+        Ok(std::iter::once(id)
+            .chain(nested)
+            .map(|bag| self.names[bag].as_str())
+            .collect())
     }
 
-    count_contained_bags(&rules, "shiny gold")
+    /// A cycle of rules that makes some bags impossible to pack, if there is one.
+    #[allow(dead_code)] // Only exercised by the tests.
+    fn find_cycle(&self) -> Option<Vec<String>> {
+        let mut memo = HashMap::new();
+        (0..self.names.len()).find_map(|bag| {
+            match self.evaluate(bag, &|_, _: &[()]| (), &mut memo, &mut Vec::new()) {
+                Err(BagError::Cycle(cycle)) => Some(cycle),
+                _ => None,
+            }
+        })
+    }
+}
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    let graph = BagGraph::parse(input.raw());
+    graph.containers(SHINY_GOLD).unwrap().len()
+}
+
+fn part_2(input: aoc::Input) -> impl ToString {
+    let graph = BagGraph::parse(input.raw());
+    graph
+        .bags_inside(SHINY_GOLD)
+        .unwrap_or_else(|err| panic!("{err:?}"))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    const EXAMPLE: &str = "light red bags contain 1 bright white bag, 2 muted yellow bags.\ndark orange bags contain 3 bright white bags, 4 muted yellow bags.\nbright white bags contain 1 shiny gold bag.\nmuted yellow bags contain 2 shiny gold bags, 9 faded blue bags.\nshiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.\ndark olive bags contain 3 faded blue bags, 4 dotted black bags.\nvibrant plum bags contain 5 faded blue bags, 6 dotted black bags.\nfaded blue bags contain no other bags.\ndotted black bags contain no other bags.";
+
     #[test]
     fn test_part_1_example() {
-        let input = "light red bags contain 1 bright white bag, 2 muted yellow bags.\ndark orange bags contain 3 bright white bags, 4 muted yellow bags.\nbright white bags contain 1 shiny gold bag.\nmuted yellow bags contain 2 shiny gold bags, 9 faded blue bags.\nshiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.\ndark olive bags contain 3 faded blue bags, 4 dotted black bags.\nvibrant plum bags contain 5 faded blue bags, 6 dotted black bags.\nfaded blue bags contain no other bags.\ndotted black bags contain no other bags.";
-        let bag_rules_regex = Regex::new(r"(\w+ \w+) bags contain (.*)").unwrap();
-        let contained_bag_regex = Regex::new(r"(\d+) (\w+ \w+) bags?").unwrap();
-        let mut rules = HashMap::new();
-
-        for line in input.lines() {
-            let captures = bag_rules_regex.captures(line).unwrap();
-            let bag_color = captures[1].to_string();
-            let contents_str = &captures[2];
-
-            let mut contents = Vec::new();
-            if contents_str != "no other bags." {
-                for contained_bag_capture in contained_bag_regex.captures_iter(contents_str) {
-                    let count = contained_bag_capture[1].parse().unwrap();
-                    let contained_color = contained_bag_capture[2].to_string();
-                    contents.push((count, contained_color));
-                }
-            }
-
-            rules.insert(bag_color, contents);
-        }
-
-        let mut cache = HashMap::new();
-        let count = rules
-            .keys()
-            .filter(|&color| {
-                color != "shiny gold" && can_contain_shiny_gold(&rules, color, &mut cache)
-            })
-            .count();
-        assert_eq!(count, 4);
+        let graph = BagGraph::parse(EXAMPLE);
+        assert_eq!(
+            graph.containers(SHINY_GOLD),
+            Ok(vec![
+                "bright white",
+                "dark orange",
+                "light red",
+                "muted yellow"
+            ])
+        );
+        assert_eq!(graph.containers("light red"), Ok(vec![]));
+        assert_eq!(
+            graph.containers("mauve"),
+            Err(BagError::UnknownColour("mauve".to_string()))
+        );
     }
 
     #[test]
     fn test_part_2_example_1() {
-        let input = "light red bags contain 1 bright white bag, 2 muted yellow bags.\ndark orange bags contain 3 bright white bags, 4 muted yellow bags.\nbright white bags contain 1 shiny gold bag.\nmuted yellow bags contain 2 shiny gold bags, 9 faded blue bags.\nshiny gold bags contain 1 dark olive bag, 2 vibrant plum bags.\ndark olive bags contain 3 faded blue bags, 4 dotted black bags.\nvibrant plum bags contain 5 faded blue bags, 6 dotted black bags.\nfaded blue bags contain no other bags.\ndotted black bags contain no other bags.";
-        let bag_rules_regex = Regex::new(r"(\w+ \w+) bags contain (.*)").unwrap();
-        let contained_bag_regex = Regex::new(r"(\d+) (\w+ \w+) bags?").unwrap();
-        let mut rules = HashMap::new();
-
-        for line in input.lines() {
-            let captures = bag_rules_regex.captures(line).unwrap();
-            let bag_color = captures[1].to_string();
-            let contents_str = &captures[2];
-
-            let mut contents = Vec::new();
-            if contents_str != "no other bags." {
-                for contained_bag_capture in contained_bag_regex.captures_iter(contents_str) {
-                    let count = contained_bag_capture[1].parse().unwrap();
-                    let contained_color = contained_bag_capture[2].to_string();
-                    contents.push((count, contained_color));
-                }
-            }
-
-            rules.insert(bag_color, contents);
-        }
-
-        assert_eq!(count_contained_bags(&rules, "shiny gold"), 32);
+        let graph = BagGraph::parse(EXAMPLE);
+        assert_eq!(graph.bags_inside(SHINY_GOLD), Ok(32));
+        assert_eq!(graph.bags_inside("faded blue"), Ok(0));
     }
 
     #[test]
     fn test_part_2_example_2() {
         let input = "shiny gold bags contain 2 dark red bags.\ndark red bags contain 2 dark orange bags.\ndark orange bags contain 2 dark yellow bags.\ndark yellow bags contain 2 dark green bags.\ndark green bags contain 2 dark blue bags.\ndark blue bags contain 2 dark violet bags.\ndark violet bags contain no other bags.";
-        let bag_rules_regex = Regex::new(r"(\w+ \w+) bags contain (.*)").unwrap();
-        let contained_bag_regex = Regex::new(r"(\d+) (\w+ \w+) bags?").unwrap();
-        let mut rules = HashMap::new();
-
-        for line in input.lines() {
-            let captures = bag_rules_regex.captures(line).unwrap();
-            let bag_color = captures[1].to_string();
-            let contents_str = &captures[2];
-
-            let mut contents = Vec::new();
-            if contents_str != "no other bags." {
-                for contained_bag_capture in contained_bag_regex.captures_iter(contents_str) {
-                    let count = contained_bag_capture[1].parse().unwrap();
-                    let contained_color = contained_bag_capture[2].to_string();
-                    contents.push((count, contained_color));
-                }
-            }
+        let graph = BagGraph::parse(input);
+        assert_eq!(graph.bags_inside(SHINY_GOLD), Ok(126));
+        assert_eq!(graph.deepest_chain(SHINY_GOLD).unwrap().len(), 7);
+    }
 
-            rules.insert(bag_color, contents);
-        }
+    #[test]
+    fn test_deepest_chain() {
+        let graph = BagGraph::parse(EXAMPLE);
+        assert_eq!(
+            graph.deepest_chain("light red"),
+            Ok(vec![
+                "light red",
+                "bright white",
+                "shiny gold",
+                "dark olive",
+                "faded blue"
+            ])
+        );
+        assert_eq!(graph.find_cycle(), None);
+    }
 
-        assert_eq!(count_contained_bags(&rules, "shiny gold"), 126);
+    #[test]
+    fn test_cycle() {
+        let input = "shiny gold bags contain 1 dark red bag.\ndark red bags contain 2 pale blue bags, 1 dull white bag.\npale blue bags contain 1 dark red bag.\ndull white bags contain no other bags.";
+        let graph = BagGraph::parse(input);
+        let cycle = vec!["dark red".to_string(), "pale blue".to_string()];
+        assert_eq!(graph.find_cycle(), Some(cycle.clone()));
+        assert_eq!(graph.bags_inside(SHINY_GOLD), Err(BagError::Cycle(cycle)));
+        assert_eq!(graph.bags_inside("dull white"), Ok(0));
+        assert_eq!(
+            graph.containers("dark red"),
+            Ok(vec!["pale blue", "shiny gold"])
+        );
     }
 }
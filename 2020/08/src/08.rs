@@ -1,6 +1,7 @@
 aoc::parts!(1, 2);
 
-use std::collections::HashSet;
+use std::collections::HashMap;
+use std::env;
 
 #[derive(Debug, Clone)]
 enum Instruction {
@@ -23,58 +24,251 @@ fn parse_instruction(line: &str) -> Instruction {
 }
 
 fn run_program(program: &[Instruction]) -> (i32, bool) {
+    run_program_patched(program, None)
+}
+
+/// Runs the program as if the `jmp`/`nop` at `patch` had been swapped, without cloning it.
+fn run_program_patched(program: &[Instruction], patch: Option<usize>) -> (i32, bool) {
+    run_program_profiled(program, patch, None)
+}
+
+/// Runs the program, recording every executed instruction into `profile` when one is given.
+fn run_program_profiled(
+    program: &[Instruction],
+    patch: Option<usize>,
+    mut profile: Option<&mut Profile>,
+) -> (i32, bool) {
     let mut accumulator = 0;
     let mut pc = 0; // Program counter
-    let mut visited = HashSet::new();
-    let mut terminated = false;
+    let mut visited = vec![false; program.len()];
 
     while pc < program.len() {
-        if visited.contains(&pc) {
+        if visited[pc] {
             return (accumulator, false); // Infinite loop detected
         }
-        visited.insert(pc);
+        visited[pc] = true;
 
-        match program[pc] {
-            Instruction::Acc(val) => {
-                accumulator += val;
-                pc += 1;
-            }
-            Instruction::Jmp(offset) => {
-                pc = (pc as i32 + offset) as usize;
-            }
-            Instruction::Nop(_) => {
-                pc += 1;
-            }
+        if let Instruction::Acc(val) = program[pc] {
+            accumulator += val;
+        }
+        let next = next_pc(program, pc, patch == Some(pc));
+        if let Some(profile) = profile.as_deref_mut() {
+            profile.record(pc, next, accumulator);
+        }
+        match next {
+            Some(next) => pc = next,
+            None => break,
         }
     }
 
     (accumulator, true) // Program terminated normally
 }
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let program: Vec<Instruction> = input.raw().lines().map(parse_instruction).collect();
-    let (accumulator_value, _) = run_program(&program);
-    accumulator_value
+/// Execution statistics gathered by `run_program_profiled`.
+#[derive(Debug, Default)]
+struct Profile {
+    counts: Vec<u64>,
+    steps: u64,
+    /// Backward jumps taken, keyed by (jump instruction, target), i.e. the loops executed.
+    back_edges: HashMap<(usize, usize), u64>,
+    accumulator_range: Option<(i32, i32)>,
 }
 
-fn part_2(input: aoc::Input) -> impl ToString {
-    let original_program: Vec<Instruction> = input.raw().lines().map(parse_instruction).collect();
-
-    for i in 0..original_program.len() {
-        let mut modified_program = original_program.clone();
-        match modified_program[i] {
-            Instruction::Jmp(val) => modified_program[i] = Instruction::Nop(val),
-            Instruction::Nop(val) => modified_program[i] = Instruction::Jmp(val),
-            _ => continue, // Skip if it's an Acc instruction
+impl Profile {
+    fn new(program_len: usize) -> Self {
+        Profile {
+            counts: vec![0; program_len],
+            ..Default::default()
         }
+    }
 
-        let (accumulator, terminated) = run_program(&modified_program);
-        if terminated {
-            return accumulator.to_string();
+    fn record(&mut self, pc: usize, next: Option<usize>, accumulator: i32) {
+        self.counts[pc] += 1;
+        self.steps += 1;
+        if let Some(next) = next.filter(|&next| next <= pc) {
+            *self.back_edges.entry((pc, next)).or_insert(0) += 1;
         }
+        let (min, max) = self.accumulator_range.unwrap_or((accumulator, accumulator));
+        self.accumulator_range = Some((min.min(accumulator), max.max(accumulator)));
     }
 
-    panic!("No solution found"); // Should ideally not reach here with valid input
+    /// Loops as (first instruction, last instruction, times taken), hottest first.
+    fn hot_loops(&self) -> Vec<(usize, usize, u64)> {
+        let mut loops: Vec<_> = self
+            .back_edges
+            .iter()
+            .map(|(&(from, to), &count)| (to, from, count))
+            .collect();
+        loops.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        loops
+    }
+
+    fn to_table(&self, program: &[Instruction]) -> String {
+        let mut table = format!(
+            "total steps: {}\n{:>5}  {:<12} {:>8}\n",
+            self.steps, "pc", "instruction", "count"
+        );
+        for (pc, &count) in self
+            .counts
+            .iter()
+            .enumerate()
+            .filter(|(_, &count)| count > 0)
+        {
+            table += &format!(
+                "{:>5}  {:<12} {:>8}\n",
+                pc,
+                format!("{:?}", program[pc]),
+                count
+            );
+        }
+        for (start, end, count) in self.hot_loops() {
+            table += &format!("loop {}..={} taken {} times\n", start, end, count);
+        }
+        if let Some((min, max)) = self.accumulator_range {
+            table += &format!("acc range: {}..={}\n", min, max);
+        }
+        table
+    }
+
+    fn to_json(&self) -> String {
+        let counts: Vec<String> = self.counts.iter().map(|count| count.to_string()).collect();
+        let loops: Vec<String> = self
+            .hot_loops()
+            .iter()
+            .map(|(start, end, count)| {
+                format!(r#"{{"start":{},"end":{},"count":{}}}"#, start, end, count)
+            })
+            .collect();
+        let registers = match self.accumulator_range {
+            Some((min, max)) => format!(r#"{{"acc":{{"min":{},"max":{}}}}}"#, min, max),
+            None => "{}".to_string(),
+        };
+        format!(
+            r#"{{"steps":{},"counts":[{}],"hot_loops":[{}],"registers":{}}}"#,
+            self.steps,
+            counts.join(","),
+            loops.join(","),
+            registers
+        )
+    }
+}
+
+/// Prints a profile of the program to stderr when `AOC_PROFILE` is set to `table` or `json`.
+fn report_profile(program: &[Instruction], patch: Option<usize>) {
+    let Ok(format) = env::var("AOC_PROFILE") else {
+        return;
+    };
+    let mut profile = Profile::new(program.len());
+    run_program_profiled(program, patch, Some(&mut profile));
+    match format.as_str() {
+        "json" => eprintln!("{}", profile.to_json()),
+        _ => eprint!("{}", profile.to_table(program)),
+    }
+}
+
+/// The instruction executed after `pc`, or `None` if control leaves the program (termination).
+fn next_pc(program: &[Instruction], pc: usize, flipped: bool) -> Option<usize> {
+    let jump = match (&program[pc], flipped) {
+        (Instruction::Jmp(offset), false) | (Instruction::Nop(offset), true) => *offset,
+        _ => 1,
+    };
+    let target = pc as i64 + jump as i64;
+    (0..program.len() as i64)
+        .contains(&target)
+        .then_some(target as usize)
+}
+
+/// Marks every instruction from which execution of the unmodified program terminates.
+///
+/// Works backwards over the reversed control-flow graph from the instructions that leave the
+/// program, so each instruction is visited once.
+fn reaches_termination(program: &[Instruction]) -> Vec<bool> {
+    let mut predecessors = vec![Vec::new(); program.len()];
+    let mut stack = Vec::new();
+    for pc in 0..program.len() {
+        match next_pc(program, pc, false) {
+            Some(next) => predecessors[next].push(pc),
+            None => stack.push(pc),
+        }
+    }
+
+    let mut terminates = vec![false; program.len()];
+    while let Some(pc) = stack.pop() {
+        if terminates[pc] {
+            continue;
+        }
+        terminates[pc] = true;
+        stack.extend(predecessors[pc].iter().copied());
+    }
+    terminates
+}
+
+/// The instructions executed by the unmodified program, in order, up to the first repeat.
+fn execution_path(program: &[Instruction]) -> Vec<usize> {
+    let mut path = Vec::new();
+    let mut visited = vec![false; program.len()];
+    let mut pc = Some(0);
+    while let Some(current) = pc.filter(|&current| current < program.len() && !visited[current]) {
+        visited[current] = true;
+        path.push(current);
+        pc = next_pc(program, current, false);
+    }
+    path
+}
+
+/// Every `jmp`/`nop` whose swap makes the program terminate.
+///
+/// Only instructions on the original execution path can change the outcome, and a swap on that
+/// path works exactly when the swapped successor reaches termination in the unmodified graph.
+fn terminating_flips(program: &[Instruction]) -> Vec<usize> {
+    let terminates = reaches_termination(program);
+    execution_path(program)
+        .into_iter()
+        .filter(|&pc| !matches!(program[pc], Instruction::Acc(_)))
+        .filter(|&pc| next_pc(program, pc, true).is_none_or(|next| terminates[next]))
+        .collect()
+}
+
+/// The single `jmp`/`nop` swap that repairs the program, found in linear time.
+fn find_repair(program: &[Instruction]) -> Option<usize> {
+    terminating_flips(program).first().copied()
+}
+
+/// The instructions forming the loop the unmodified program gets stuck in, starting from the
+/// first instruction that is executed twice. Empty if the program terminates.
+fn infinite_loop(program: &[Instruction]) -> Vec<usize> {
+    let path = execution_path(program);
+    let Some(&last) = path.last() else {
+        return Vec::new();
+    };
+    match next_pc(program, last, false) {
+        Some(repeat) => {
+            let start = path.iter().position(|&pc| pc == repeat).unwrap();
+            path[start..].to_vec()
+        }
+        None => Vec::new(),
+    }
+}
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    let program: Vec<Instruction> = input.raw().lines().map(parse_instruction).collect();
+    let (accumulator_value, _) = run_program(&program);
+    report_profile(&program, None);
+    accumulator_value
+}
+
+fn part_2(input: aoc::Input) -> impl ToString {
+    let program: Vec<Instruction> = input.raw().lines().map(parse_instruction).collect();
+    let repair = find_repair(&program).unwrap_or_else(|| {
+        panic!(
+            "No solution found, stuck in loop {:?}",
+            infinite_loop(&program)
+        )
+    });
+    let (accumulator, terminated) = run_program_patched(&program, Some(repair));
+    assert!(terminated);
+    report_profile(&program, Some(repair));
+    accumulator
 }
 
 #[cfg(test)]
@@ -134,4 +328,57 @@ mod tests {
             }
         }
     }
+
+    fn example_program() -> Vec<Instruction> {
+        let input = "nop +0\nacc +1\njmp +4\nacc +3\njmp -3\nacc -99\nacc +1\njmp -4\nacc +6";
+        input.lines().map(parse_instruction).collect()
+    }
+
+    #[test]
+    fn test_find_repair() {
+        let program = example_program();
+        assert_eq!(find_repair(&program), Some(7));
+        assert_eq!(run_program_patched(&program, Some(7)), (8, true));
+    }
+
+    #[test]
+    fn test_terminating_flips() {
+        let program = example_program();
+        assert_eq!(terminating_flips(&program), vec![7]);
+
+        // Both swaps lead out of the program here.
+        let program: Vec<Instruction> = "nop +2\njmp -1\nacc +0"
+            .lines()
+            .map(parse_instruction)
+            .collect();
+        assert_eq!(terminating_flips(&program), vec![0, 1]);
+    }
+
+    #[test]
+    fn test_infinite_loop() {
+        let program = example_program();
+        assert_eq!(infinite_loop(&program), vec![1, 2, 6, 7, 3, 4]);
+
+        let mut repaired = program.clone();
+        repaired[7] = Instruction::Nop(-4);
+        assert!(infinite_loop(&repaired).is_empty());
+    }
+
+    #[test]
+    fn test_profile() {
+        let program = example_program();
+        let mut profile = Profile::new(program.len());
+        assert_eq!(
+            run_program_profiled(&program, None, Some(&mut profile)),
+            (5, false)
+        );
+        assert_eq!(profile.steps, 7);
+        assert_eq!(profile.counts, vec![1, 1, 1, 1, 1, 0, 1, 1, 0]);
+        assert_eq!(profile.hot_loops(), vec![(1, 4, 1), (3, 7, 1)]);
+        assert_eq!(profile.accumulator_range, Some((0, 5)));
+        assert_eq!(
+            profile.to_json(),
+            r#"{"steps":7,"counts":[1,1,1,1,1,0,1,1,0],"hot_loops":[{"start":1,"end":4,"count":1},{"start":3,"end":7,"count":1}],"registers":{"acc":{"min":0,"max":5}}}"#
+        );
+    }
 }
@@ -2,93 +2,178 @@ aoc::parts!(1, 2);
 
 use std::collections::HashMap;
 
-fn apply_mask_v2(address: u64, mask: &str) -> Vec<u64> {
-    let mut addresses = vec![0];
-    for (i, c) in mask.chars().rev().enumerate() {
-        match c {
-            '1' => {
-                for addr in &mut addresses {
-                    *addr |= 1 << i;
-                }
-            }
-            'X' => {
-                let mut new_addresses = Vec::new();
-                for addr in &addresses {
-                    let mut new_addr = *addr;
-                    new_addr &= !(1 << i);
-                    new_addresses.push(new_addr);
-                    let mut new_addr2 = *addr;
-                    new_addr2 |= 1 << i;
-                    new_addresses.push(new_addr2);
-                }
-                addresses = new_addresses;
-            }
-            _ => {
-                for addr in &mut addresses {
-                    if (address >> i) & 1 == 1 {
-                        *addr |= 1 << i;
-                    } else {
-                        *addr &= !(1 << i);
-                    }
-                }
+/// A bitmask of `width` bits, split into the bits it forces to 1, forces to 0, and leaves floating.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Mask {
+    ones: u64,
+    zeros: u64,
+    floating: u64,
+    width: u32,
+}
+
+impl Mask {
+    fn parse(mask: &str) -> Result<Mask, String> {
+        let width = mask.len() as u32;
+        if !(1..=64).contains(&width) {
+            return Err(format!("mask {mask:?} must be 1 to 64 bits wide"));
+        }
+        let mut parsed = Mask {
+            ones: 0,
+            zeros: 0,
+            floating: 0,
+            width,
+        };
+        for (i, c) in mask.bytes().rev().enumerate() {
+            match c {
+                b'1' => parsed.ones |= 1 << i,
+                b'0' => parsed.zeros |= 1 << i,
+                b'X' => parsed.floating |= 1 << i,
+                _ => return Err(format!("invalid character {:?} in mask", c as char)),
             }
         }
+        Ok(parsed)
+    }
+
+    fn fits(&self, value: u64) -> bool {
+        self.width == 64 || value >> self.width == 0
+    }
+
+    /// Decoder version 1: the mask overwrites the value's bits, except where it is floating.
+    fn apply(&self, value: u64) -> u64 {
+        (value | self.ones) & !self.zeros
     }
-    addresses
-}
 
-fn apply_mask(value: u64, mask: &str) -> u64 {
-    let mut result = value;
-    for (i, c) in mask.chars().rev().enumerate() {
-        match c {
-            '1' => result |= 1 << i,
-            '0' => result &= !(1 << i),
-            _ => {}
+    /// Decoder version 2: the addresses the mask makes out of `address`.
+    fn address_pattern(&self, address: u64) -> Pattern {
+        Pattern {
+            fixed: (address | self.ones) & !self.floating,
+            floating: self.floating,
         }
     }
-    result
 }
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let lines: Vec<&str> = input.as_lines().into_iter().copied().collect();
-    let mut memory: HashMap<u64, u64> = HashMap::new();
-    let mut current_mask = "";
-
-    for line in lines {
-        let parts: Vec<&str> = line.split(" = ").collect();
-        if parts[0] == "mask" {
-            current_mask = parts[1];
-        } else {
-            let address = parts[0][4..parts[0].len() - 1].parse::<u64>().unwrap();
-            let value = parts[1].parse::<u64>().unwrap();
-            let masked_value = apply_mask(value, current_mask);
-            memory.insert(address, masked_value);
+/// A set of addresses: every bit in `floating` takes both values, the others are as in `fixed`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct Pattern {
+    /// Always 0 at the floating bits, so equal patterns compare equal.
+    fixed: u64,
+    floating: u64,
+}
+
+impl Pattern {
+    fn count(&self) -> u128 {
+        1 << self.floating.count_ones()
+    }
+
+    /// The addresses in both patterns, if any.
+    fn intersect(&self, other: &Pattern) -> Option<Pattern> {
+        let both_fixed = !self.floating & !other.floating;
+        if (self.fixed ^ other.fixed) & both_fixed != 0 {
+            return None;
         }
+        Some(Pattern {
+            fixed: self.fixed | other.fixed,
+            floating: self.floating & other.floating,
+        })
     }
+}
 
-    memory.values().sum::<u64>()
+#[derive(Debug, PartialEq, Eq)]
+enum Instruction {
+    Mask(Mask),
+    Write { address: u64, value: u64 },
 }
 
-fn part_2(input: aoc::Input) -> impl ToString {
-    let lines: Vec<&str> = input.as_lines().into_iter().copied().collect();
+/// Parses the program, checking every address and value fits in the mask in force for it.
+fn parse_program(input: &str) -> Result<Vec<Instruction>, String> {
+    let mut mask: Option<Mask> = None;
+    let mut program = Vec::new();
+    for line in input.lines() {
+        let (target, value) = line
+            .split_once(" = ")
+            .ok_or_else(|| format!("invalid line {line:?}"))?;
+        if target == "mask" {
+            let parsed = Mask::parse(value)?;
+            mask = Some(parsed);
+            program.push(Instruction::Mask(parsed));
+            continue;
+        }
+
+        let address = target
+            .strip_prefix("mem[")
+            .and_then(|rest| rest.strip_suffix(']'))
+            .and_then(|address| address.parse().ok())
+            .ok_or_else(|| format!("invalid memory target {target:?}"))?;
+        let value = value
+            .parse()
+            .map_err(|_| format!("invalid value {value:?}"))?;
+        let mask = mask.ok_or_else(|| format!("{line:?} comes before any mask"))?;
+        if !mask.fits(address) || !mask.fits(value) {
+            return Err(format!("{line:?} does not fit in {} bits", mask.width));
+        }
+        program.push(Instruction::Write { address, value });
+    }
+    Ok(program)
+}
+
+/// Sum of memory after running the program with decoder version 1.
+fn run_v1(program: &[Instruction]) -> u128 {
     let mut memory: HashMap<u64, u64> = HashMap::new();
-    let mut current_mask = "";
-
-    for line in lines {
-        let parts: Vec<&str> = line.split(" = ").collect();
-        if parts[0] == "mask" {
-            current_mask = parts[1];
-        } else {
-            let address = parts[0][4..parts[0].len() - 1].parse::<u64>().unwrap();
-            let value = parts[1].parse::<u64>().unwrap();
-            let masked_addresses = apply_mask_v2(address, current_mask);
-            for masked_address in masked_addresses {
-                memory.insert(masked_address, value);
+    let mut current_mask = None;
+    for instruction in program {
+        match *instruction {
+            Instruction::Mask(mask) => current_mask = Some(mask),
+            Instruction::Write { address, value } => {
+                memory.insert(address, current_mask.unwrap().apply(value));
             }
         }
     }
+    memory.values().map(|&value| value as u128).sum()
+}
+
+/// Sum of memory after running the program with decoder version 2.
+///
+/// Memory is a weighted set of address patterns whose weights add up to the value at each
+/// address. A write to a pattern first cancels out every existing pattern where they overlap,
+/// then adds its own, so no address is ever enumerated.
+fn run_v2(program: &[Instruction]) -> u128 {
+    let mut weights: HashMap<Pattern, i128> = HashMap::new();
+    let mut current_mask = None;
+    for instruction in program {
+        let (address, value) = match *instruction {
+            Instruction::Mask(mask) => {
+                current_mask = Some(mask);
+                continue;
+            }
+            Instruction::Write { address, value } => (address, value),
+        };
+
+        let written = current_mask.unwrap().address_pattern(address);
+        let overlaps: Vec<_> = weights
+            .iter()
+            .filter_map(|(pattern, &weight)| Some((pattern.intersect(&written)?, weight)))
+            .collect();
+        for (overlap, weight) in overlaps {
+            *weights.entry(overlap).or_default() -= weight;
+        }
+        *weights.entry(written).or_default() += value as i128;
+        weights.retain(|_, weight| *weight != 0);
+    }
 
-    memory.values().sum::<u64>()
+    // A single term can be as large as 2^64 addresses times a 64 bit value, so sum modulo 2^128.
+    // The true total always fits in a u128, which makes the wrapped result exact.
+    weights
+        .iter()
+        .map(|(pattern, &weight)| (weight as u128).wrapping_mul(pattern.count()))
+        .fold(0u128, u128::wrapping_add)
+}
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    run_v1(&parse_program(input.raw()).unwrap())
+}
+
+fn part_2(input: aoc::Input) -> impl ToString {
+    run_v2(&parse_program(input.raw()).unwrap())
 }
 
 #[cfg(test)]
@@ -97,73 +182,91 @@ mod tests {
 
     #[test]
     fn test_apply_mask() {
-        let mask = "XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X";
-        assert_eq!(apply_mask(11, mask), 73);
-        assert_eq!(apply_mask(101, mask), 101);
-        assert_eq!(apply_mask(0, mask), 64);
+        let mask = Mask::parse("XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X").unwrap();
+        assert_eq!(mask.apply(11), 73);
+        assert_eq!(mask.apply(101), 101);
+        assert_eq!(mask.apply(0), 64);
     }
 
     #[test]
     fn test_part_1() {
         let input =
             "mask = XXXXXXXXXXXXXXXXXXXXXXXXXXXXX1XXXX0X\nmem[8] = 11\nmem[7] = 101\nmem[8] = 0";
-        let lines: Vec<&str> = input.split('\n').collect();
-        let mut memory: HashMap<u64, u64> = HashMap::new();
-        let mut current_mask = "";
-        for line in lines {
-            let parts: Vec<&str> = line.split(" = ").collect();
-            if parts[0] == "mask" {
-                current_mask = parts[1];
-            } else {
-                let address = parts[0][4..parts[0].len() - 1].parse::<u64>().unwrap();
-                let value = parts[1].parse::<u64>().unwrap();
-                let masked_value = apply_mask(value, current_mask);
-                memory.insert(address, masked_value);
-            }
-        }
-        assert_eq!(memory.values().sum::<u64>(), 165);
+        assert_eq!(run_v1(&parse_program(input).unwrap()), 165);
     }
 
     #[test]
-    fn test_apply_mask_v2() {
-        let mask = "000000000000000000000000000000X1001X";
-        let addresses = apply_mask_v2(42, mask);
-        assert!(addresses.contains(&26));
-        assert!(addresses.contains(&27));
-        assert!(addresses.contains(&58));
-        assert!(addresses.contains(&59));
-
-        let mask = "00000000000000000000000000000000X0XX";
-        let addresses = apply_mask_v2(26, mask);
-        assert!(addresses.contains(&16));
-        assert!(addresses.contains(&17));
-        assert!(addresses.contains(&18));
-        assert!(addresses.contains(&19));
-        assert!(addresses.contains(&24));
-        assert!(addresses.contains(&25));
-        assert!(addresses.contains(&26));
-        assert!(addresses.contains(&27));
+    fn test_address_pattern() {
+        let mask = Mask::parse("000000000000000000000000000000X1001X").unwrap();
+        let pattern = mask.address_pattern(42);
+        // 26, 27, 58 and 59.
+        assert_eq!(
+            pattern,
+            Pattern {
+                fixed: 26,
+                floating: 0b100001
+            }
+        );
+        assert_eq!(pattern.count(), 4);
+
+        let mask = Mask::parse("00000000000000000000000000000000X0XX").unwrap();
+        let other = mask.address_pattern(26);
+        assert_eq!(other.count(), 8);
+        // 26 and 27.
+        assert_eq!(
+            pattern.intersect(&other),
+            Some(Pattern {
+                fixed: 26,
+                floating: 1
+            })
+        );
+        assert_eq!(pattern.intersect(&mask.address_pattern(0)), None);
     }
 
     #[test]
     fn test_part_2() {
         let input = "mask = 000000000000000000000000000000X1001X\nmem[42] = 100\nmask = 00000000000000000000000000000000X0XX\nmem[26] = 1";
-        let lines: Vec<&str> = input.split('\n').collect();
-        let mut memory: HashMap<u64, u64> = HashMap::new();
-        let mut current_mask = "";
-        for line in lines {
-            let parts: Vec<&str> = line.split(" = ").collect();
-            if parts[0] == "mask" {
-                current_mask = parts[1];
-            } else {
-                let address = parts[0][4..parts[0].len() - 1].parse::<u64>().unwrap();
-                let value = parts[1].parse::<u64>().unwrap();
-                let masked_addresses = apply_mask_v2(address, current_mask);
-                for masked_address in masked_addresses {
-                    memory.insert(masked_address, value);
+        assert_eq!(run_v2(&parse_program(input).unwrap()), 208);
+    }
+
+    #[test]
+    fn test_wide_masks() {
+        // Every address of a 64 bit memory, then overwrite half of it.
+        let floating = "X".repeat(64);
+        let half = format!("1{}", "X".repeat(63));
+        let input = format!("mask = {floating}\nmem[0] = 1\nmask = {half}\nmem[0] = 3");
+        assert_eq!(run_v2(&parse_program(&input).unwrap()), (1 << 63) * 4);
+
+        // The largest value written everywhere, then partly overwritten.
+        let input = format!("mask = {floating}\nmem[0] = {}", 1u64 << 63);
+        assert_eq!(run_v2(&parse_program(&input).unwrap()), 1 << 127);
+        let input = format!(
+            "mask = {floating}\nmem[0] = {max}\nmask = {half}\nmem[0] = {max}\nmem[0] = 1",
+            max = u64::MAX
+        );
+        assert_eq!(
+            run_v2(&parse_program(&input).unwrap()),
+            (1 << 63) * u64::MAX as u128 + (1 << 63)
+        );
+    }
+
+    #[test]
+    fn test_validation() {
+        assert!(Mask::parse("").is_err());
+        assert!(Mask::parse(&"X".repeat(65)).is_err());
+        assert!(Mask::parse("X1Y0").is_err());
+        assert_eq!(
+            parse_program("mask = X10X\nmem[15] = 3"),
+            Ok(vec![
+                Instruction::Mask(Mask::parse("X10X").unwrap()),
+                Instruction::Write {
+                    address: 15,
+                    value: 3
                 }
-            }
-        }
-        assert_eq!(memory.values().sum::<u64>(), 208);
+            ])
+        );
+        assert!(parse_program("mask = X10X\nmem[16] = 3").is_err());
+        assert!(parse_program("mask = X10X\nmem[1] = 16").is_err());
+        assert!(parse_program("mem[1] = 1").is_err());
     }
 }
@@ -2,37 +2,103 @@ use std::collections::HashMap;
 
 aoc::parts!(1, 2);
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let line = input.as_lines()[0];
-    let starting_numbers: Vec<u32> = line.split(',').map(|s| s.parse().unwrap()).collect();
-    solve(starting_numbers, 2020)
+/// Numbers below this have their last turn kept in a flat array; larger ones, which are spoken
+/// far more rarely, go in a map. The array never grows past this many entries, while the map
+/// grows with the number of distinct large numbers spoken.
+const DENSE_LIMIT: u32 = 1 << 22;
+
+/// The turn each number was last spoken on.
+struct LastSpoken {
+    /// Indexed by number, with 0 for never spoken as turns count from 1.
+    dense: Vec<u32>,
+    sparse: HashMap<u32, u32>,
+}
+
+impl LastSpoken {
+    fn new(dense_len: u32) -> LastSpoken {
+        LastSpoken {
+            dense: vec![0; dense_len.min(DENSE_LIMIT) as usize],
+            sparse: HashMap::new(),
+        }
+    }
+
+    /// Records `number` as spoken on `turn`, returning the turn it was spoken before that.
+    fn replace(&mut self, number: u32, turn: u32) -> Option<u32> {
+        match self.dense.get_mut(number as usize) {
+            Some(slot) => Some(std::mem::replace(slot, turn)).filter(|&t| t != 0),
+            None => self.sparse.insert(number, turn),
+        }
+    }
 }
 
-fn solve(starting_numbers: Vec<u32>, nth_number: u32) -> u32 {
-    let mut spoken_numbers: Vec<i32> = vec![-1; nth_number as usize];
-    let mut last_number_spoken: u32 = 0;
+/// The numbers spoken in the memory game, starting with `starting`.
+///
+/// Taking a prefix of it gives the whole sequence spoken so far, e.g. for debugging.
+struct VanEck<'a> {
+    starting: &'a [u32],
+    last_spoken: LastSpoken,
+    turn: u32,
+    next: u32,
+}
 
-    for (turn, &number) in starting_numbers.iter().enumerate() {
-        spoken_numbers[number as usize] = turn as i32 + 1;
-        last_number_spoken = number;
+impl<'a> VanEck<'a> {
+    /// `turns` is only a hint for how much to allocate.
+    fn new(starting: &'a [u32], turns: u32) -> VanEck<'a> {
+        VanEck {
+            starting,
+            last_spoken: LastSpoken::new(turns),
+            turn: 0,
+            next: 0,
+        }
     }
+}
 
-    for turn in starting_numbers.len() as u32..nth_number {
-        let next_number = match spoken_numbers[last_number_spoken as usize] {
-            -1 => 0,
-            last_turn_spoken => turn - last_turn_spoken as u32,
+impl Iterator for VanEck<'_> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<u32> {
+        let turn = self.turn.checked_add(1)?;
+        let number = match self.starting.get(self.turn as usize) {
+            Some(&number) => number,
+            None => self.next,
+        };
+        self.next = match self.last_spoken.replace(number, turn) {
+            Some(previous) => turn - previous,
+            None => 0,
         };
-        spoken_numbers[last_number_spoken as usize] = turn as i32;
-        last_number_spoken = next_number;
+        self.turn = turn;
+        Some(number)
     }
+}
 
-    last_number_spoken
+fn parse_starting_numbers(line: &str) -> Result<Vec<u32>, String> {
+    line.trim()
+        .split(',')
+        .map(|s| {
+            s.trim()
+                .parse()
+                .map_err(|_| format!("invalid starting number {s:?}"))
+        })
+        .collect()
+}
+
+/// The number spoken on turn `nth_number`, counting from 1.
+fn solve(starting_numbers: &[u32], nth_number: u32) -> Option<u32> {
+    if starting_numbers.is_empty() {
+        return None;
+    }
+    let index = nth_number.checked_sub(1)?;
+    VanEck::new(starting_numbers, nth_number).nth(index as usize)
+}
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    let starting_numbers = parse_starting_numbers(input.as_lines()[0]).unwrap();
+    solve(&starting_numbers, 2020).unwrap()
 }
 
 fn part_2(input: aoc::Input) -> impl ToString {
-    let line = input.as_lines()[0];
-    let starting_numbers: Vec<u32> = line.split(',').map(|s| s.parse().unwrap()).collect();
-    solve(starting_numbers, 30000000)
+    let starting_numbers = parse_starting_numbers(input.as_lines()[0]).unwrap();
+    solve(&starting_numbers, 30000000).unwrap()
 }
 
 #[cfg(test)]
@@ -41,23 +107,39 @@ mod tests {
 
     #[test]
     fn test_examples() {
-        assert_eq!(solve(vec![0, 3, 6], 2020), 436);
-        assert_eq!(solve(vec![1, 3, 2], 2020), 1);
-        assert_eq!(solve(vec![2, 1, 3], 2020), 10);
-        assert_eq!(solve(vec![1, 2, 3], 2020), 27);
-        assert_eq!(solve(vec![2, 3, 1], 2020), 78);
-        assert_eq!(solve(vec![3, 2, 1], 2020), 438);
-        assert_eq!(solve(vec![3, 1, 2], 2020), 1836);
+        assert_eq!(solve(&[0, 3, 6], 2020), Some(436));
+        assert_eq!(solve(&[1, 3, 2], 2020), Some(1));
+        assert_eq!(solve(&[2, 1, 3], 2020), Some(10));
+        assert_eq!(solve(&[1, 2, 3], 2020), Some(27));
+        assert_eq!(solve(&[2, 3, 1], 2020), Some(78));
+        assert_eq!(solve(&[3, 2, 1], 2020), Some(438));
+        assert_eq!(solve(&[3, 1, 2], 2020), Some(1836));
     }
 
     #[test]
     fn test_examples_part_2() {
-        assert_eq!(solve(vec![0, 3, 6], 30000000), 175594);
-        assert_eq!(solve(vec![1, 3, 2], 30000000), 2578);
-        assert_eq!(solve(vec![2, 1, 3], 30000000), 3544142);
-        assert_eq!(solve(vec![1, 2, 3], 30000000), 261214);
-        assert_eq!(solve(vec![2, 3, 1], 30000000), 6895259);
-        assert_eq!(solve(vec![3, 2, 1], 30000000), 18);
-        assert_eq!(solve(vec![3, 1, 2], 30000000), 362);
+        assert_eq!(solve(&[0, 3, 6], 30000000), Some(175594));
+        assert_eq!(solve(&[1, 3, 2], 30000000), Some(2578));
+        assert_eq!(solve(&[2, 1, 3], 30000000), Some(3544142));
+        assert_eq!(solve(&[1, 2, 3], 30000000), Some(261214));
+        assert_eq!(solve(&[2, 3, 1], 30000000), Some(6895259));
+        assert_eq!(solve(&[3, 2, 1], 30000000), Some(18));
+        assert_eq!(solve(&[3, 1, 2], 30000000), Some(362));
+    }
+
+    #[test]
+    fn test_edge_cases() {
+        let prefix: Vec<_> = VanEck::new(&[0, 3, 6], 10).take(10).collect();
+        assert_eq!(prefix, [0, 3, 6, 0, 3, 3, 1, 0, 4, 0]);
+
+        // Starting numbers larger than the number of turns, and turns within the starting numbers.
+        assert_eq!(solve(&[4_000_000_000, 7], 2), Some(7));
+        assert_eq!(solve(&[4_000_000_000, 7, 4_000_000_000], 4), Some(2));
+        assert_eq!(solve(&[0, 3, 6], 0), None);
+        assert_eq!(solve(&[], 10), None);
+
+        assert_eq!(parse_starting_numbers("0,3,6\n"), Ok(vec![0, 3, 6]));
+        assert!(parse_starting_numbers("0,-3").is_err());
+        assert!(parse_starting_numbers("").is_err());
     }
 }
@@ -1,89 +1,202 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::ops::RangeInclusive;
 
 aoc::parts!(1, 2);
 
-// fn part_2(input: aoc::Input) -> impl ToString {
-//     0
-// }
-
 fn part_2(input: aoc::Input) -> impl ToString {
-    let sections: Vec<Vec<&str>> = input
-        .as_lines()
-        .into_iter()
-        .copied()
-        .collect::<Vec<&str>>()
-        .split(|line| line.is_empty())
-        .map(|s| s.to_vec())
-        .collect();
-    let rules = parse_rules(sections[0].join("\n").as_str());
-    let my_ticket = parse_tickets(sections[1].join("\n").as_str())
-        .first()
-        .unwrap()
-        .clone();
-    let nearby_tickets = parse_tickets(sections[2].join("\n").as_str());
-
-    // Filter out invalid tickets
-    let valid_tickets: Vec<Vec<u32>> = nearby_tickets
-        .into_iter()
-        .filter(|ticket| {
-            ticket
-                .iter()
-                .all(|&value| is_valid_for_any_rule(value, &rules))
-        })
-        .collect();
+    let notes = parse_notes(input.raw());
+    let valid_tickets = valid_tickets(&notes);
 
-    // Solve for field mappings
-    let field_mapping = solve_part_2(&rules, &valid_tickets);
+    let field_mapping = assign_fields(&notes.rules, &valid_tickets).unwrap_or_else(|err| {
+        panic!(
+            "{}\n{}",
+            err.describe(&notes.rules),
+            elimination_report(&notes.rules, &valid_tickets, err.position())
+        )
+    });
 
     // Calculate product of departure fields on my ticket
     let mut product = 1;
     for (field_name, index) in field_mapping {
         if field_name.starts_with("departure") {
-            product *= my_ticket[index] as u64;
+            product *= notes.my_ticket[index] as u64;
         }
     }
 
     product
 }
 
-fn solve_part_2(rules: &[Rule], valid_tickets: &[Vec<u32>]) -> HashMap<String, usize> {
-    let num_fields = valid_tickets[0].len();
-    let mut possible_fields: Vec<HashSet<String>> = vec![HashSet::new(); num_fields];
+/// Set of rule indices, one bit per rule.
+type Candidates = u64;
+
+/// For each position on the tickets, the rules that every ticket's value there satisfies.
+fn candidate_fields(rules: &[Rule], tickets: &[Vec<u32>]) -> Vec<Candidates> {
+    assert!(rules.len() <= 64, "too many rules for a u64 candidate set");
+    let all: Candidates = (0..rules.len()).fold(0, |set, rule| set | 1 << rule);
+    let mut candidates = vec![all; tickets.first().map_or(0, Vec::len)];
 
-    // Initialize possible fields for each position
-    for i in 0..num_fields {
-        for rule in rules {
-            possible_fields[i].insert(rule.name.clone());
+    for ticket in tickets {
+        for (position, &value) in ticket.iter().enumerate() {
+            for (rule_index, rule) in rules.iter().enumerate() {
+                if !rule.matches(value) {
+                    candidates[position] &= !(1 << rule_index);
+                }
+            }
         }
     }
 
-    // Eliminate impossible fields
-    for ticket in valid_tickets {
-        for (i, &value) in ticket.iter().enumerate() {
-            for rule in rules {
-                if !rule.ranges.iter().any(|range| range.contains(&value)) {
-                    possible_fields[i].remove(&rule.name);
-                }
+    candidates
+}
+
+/// Why fields could not be assigned to positions.
+#[derive(Debug, PartialEq, Eq)]
+enum FieldError {
+    /// No rule is left for this position once the others have theirs.
+    Unsolvable { position: usize },
+    /// Either of these two rules fits this position, with the others still matched.
+    Ambiguous {
+        position: usize,
+        rules: (usize, usize),
+    },
+}
+
+impl FieldError {
+    fn position(&self) -> usize {
+        match *self {
+            FieldError::Unsolvable { position } | FieldError::Ambiguous { position, .. } => {
+                position
             }
         }
     }
 
-    // Deduce field order
-    let mut field_mapping: HashMap<String, usize> = HashMap::new();
-    while field_mapping.len() < num_fields {
-        for i in 0..num_fields {
-            if possible_fields[i].len() == 1 {
-                let field_name = possible_fields[i].iter().next().unwrap().clone();
-                field_mapping.insert(field_name.clone(), i);
-                for j in 0..num_fields {
-                    possible_fields[j].remove(&field_name);
-                }
+    fn describe(&self, rules: &[Rule]) -> String {
+        match *self {
+            FieldError::Unsolvable { position } => {
+                format!("no field can be assigned to position {position}")
             }
+            FieldError::Ambiguous {
+                position,
+                rules: (a, b),
+            } => format!(
+                "position {position} could be either {} or {}",
+                rules[a].name, rules[b].name
+            ),
+        }
+    }
+}
+
+/// Tries to give `position` a rule from its candidates, moving other positions to different rules
+/// along an augmenting path if needed. `owner[rule]` is the position currently holding `rule`.
+fn augment(
+    position: usize,
+    candidates: &[Candidates],
+    owner: &mut [Option<usize>],
+    visited: &mut Candidates,
+) -> bool {
+    let mut remaining = candidates[position] & !*visited;
+    while remaining != 0 {
+        let rule = remaining.trailing_zeros() as usize;
+        remaining &= remaining - 1;
+        *visited |= 1 << rule;
+        let free = match owner[rule] {
+            None => true,
+            Some(other) => augment(other, candidates, owner, visited),
+        };
+        if free {
+            owner[rule] = Some(position);
+            return true;
         }
     }
+    false
+}
+
+/// Matches every position to a distinct rule, as a bipartite matching between them.
+///
+/// The matching must be the only one: once found, each position in turn gives up its rule and
+/// looks for an augmenting path to another. If there is one, the notes do not decide the fields.
+fn match_positions(candidates: &[Candidates], rule_count: usize) -> Result<Vec<usize>, FieldError> {
+    let mut owner = vec![None; rule_count];
+    for position in 0..candidates.len() {
+        if !augment(position, candidates, &mut owner, &mut 0) {
+            return Err(FieldError::Unsolvable { position });
+        }
+    }
+
+    let mut assigned = vec![0; candidates.len()];
+    for (rule, position) in owner.iter().enumerate() {
+        if let Some(position) = *position {
+            assigned[position] = rule;
+        }
+    }
+
+    for (position, &rule) in assigned.iter().enumerate() {
+        let mut owner = owner.clone();
+        owner[rule] = None;
+        let mut without_rule = candidates.to_vec();
+        without_rule[position] &= !(1 << rule);
+        if augment(position, &without_rule, &mut owner, &mut 0) {
+            let other = owner.iter().position(|&o| o == Some(position)).unwrap();
+            return Err(FieldError::Ambiguous {
+                position,
+                rules: (rule.min(other), rule.max(other)),
+            });
+        }
+    }
+
+    Ok(assigned)
+}
+
+/// Maps each field name to its position on the tickets.
+fn assign_fields(
+    rules: &[Rule],
+    valid_tickets: &[Vec<u32>],
+) -> Result<HashMap<String, usize>, FieldError> {
+    let candidates = candidate_fields(rules, valid_tickets);
+    let assigned = match_positions(&candidates, rules.len())?;
+    Ok(assigned
+        .into_iter()
+        .enumerate()
+        .map(|(position, rule)| (rules[rule].name.clone(), position))
+        .collect())
+}
+
+/// A value on a ticket that ruled a field out of its position.
+#[derive(Debug, PartialEq, Eq)]
+struct Elimination {
+    rule: usize,
+    ticket: usize,
+    value: u32,
+}
+
+/// The first value at `position` to rule out each rule that does not fit there.
+fn eliminations(rules: &[Rule], tickets: &[Vec<u32>], position: usize) -> Vec<Elimination> {
+    rules
+        .iter()
+        .enumerate()
+        .filter_map(|(rule_index, rule)| {
+            tickets.iter().enumerate().find_map(|(ticket, values)| {
+                let value = values[position];
+                (!rule.matches(value)).then_some(Elimination {
+                    rule: rule_index,
+                    ticket,
+                    value,
+                })
+            })
+        })
+        .collect()
+}
 
-    field_mapping
+fn elimination_report(rules: &[Rule], tickets: &[Vec<u32>], position: usize) -> String {
+    eliminations(rules, tickets, position)
+        .iter()
+        .map(|e| {
+            format!(
+                "{} ruled out at position {position} by {} on ticket {}",
+                rules[e.rule].name, e.value, e.ticket
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
 #[derive(Debug)]
@@ -112,6 +225,41 @@ fn parse_rules(rules_str: &str) -> Vec<Rule> {
         .collect()
 }
 
+impl Rule {
+    fn matches(&self, value: u32) -> bool {
+        self.ranges.iter().any(|range| range.contains(&value))
+    }
+}
+
+struct Notes {
+    rules: Vec<Rule>,
+    my_ticket: Vec<u32>,
+    nearby_tickets: Vec<Vec<u32>>,
+}
+
+fn parse_notes(input: &str) -> Notes {
+    let sections: Vec<&str> = input.trim().split("\n\n").collect();
+    Notes {
+        rules: parse_rules(sections[0]),
+        my_ticket: parse_tickets(sections[1]).remove(0),
+        nearby_tickets: parse_tickets(sections[2]),
+    }
+}
+
+/// Nearby tickets whose every value fits at least one rule.
+fn valid_tickets(notes: &Notes) -> Vec<Vec<u32>> {
+    notes
+        .nearby_tickets
+        .iter()
+        .filter(|ticket| {
+            ticket
+                .iter()
+                .all(|&value| is_valid_for_any_rule(value, &notes.rules))
+        })
+        .cloned()
+        .collect()
+}
+
 fn parse_tickets(tickets_str: &str) -> Vec<Vec<u32>> {
     tickets_str
         .lines()
@@ -121,27 +269,16 @@ fn parse_tickets(tickets_str: &str) -> Vec<Vec<u32>> {
 }
 
 fn is_valid_for_any_rule(value: u32, rules: &[Rule]) -> bool {
-    rules
-        .iter()
-        .any(|rule| rule.ranges.iter().any(|range| range.contains(&value)))
+    rules.iter().any(|rule| rule.matches(value))
 }
 
 fn part_1(input: aoc::Input) -> impl ToString {
-    let sections: Vec<Vec<&str>> = input
-        .as_lines()
-        .into_iter()
-        .copied()
-        .collect::<Vec<&str>>()
-        .split(|line| line.is_empty())
-        .map(|s| s.to_vec())
-        .collect();
-    let rules = parse_rules(sections[0].join("\n").as_str());
-    let nearby_tickets = parse_tickets(sections[2].join("\n").as_str());
+    let notes = parse_notes(input.raw());
 
     let mut error_rate = 0;
-    for ticket in nearby_tickets {
+    for ticket in notes.nearby_tickets {
         for value in ticket {
-            if !is_valid_for_any_rule(value, &rules) {
+            if !is_valid_for_any_rule(value, &notes.rules) {
                 error_rate += value;
             }
         }
@@ -179,30 +316,45 @@ mod tests {
     #[test]
     fn test_part_2() {
         let input = "class: 0-1 or 4-19\nrow: 0-5 or 8-19\nseat: 0-13 or 16-19\n\nyour ticket:\n11,12,13\n\nnearby tickets:\n3,9,18\n15,1,5\n5,14,9";
-        let sections: Vec<Vec<&str>> = input
-            .split("\n\n")
-            .map(|s| s.lines().collect::<Vec<&str>>())
-            .collect();
-        let rules = parse_rules(sections[0].join("\n").as_str());
-        let my_ticket = parse_tickets(sections[1].join("\n").as_str())
-            .first()
-            .unwrap()
-            .clone();
-        let nearby_tickets = parse_tickets(sections[2].join("\n").as_str());
-
-        let valid_tickets: Vec<Vec<u32>> = nearby_tickets
-            .into_iter()
-            .filter(|ticket| {
-                ticket
-                    .iter()
-                    .all(|&value| is_valid_for_any_rule(value, &rules))
-            })
-            .collect();
+        let notes = parse_notes(input);
+        let valid_tickets = valid_tickets(&notes);
 
-        let field_mapping = solve_part_2(&rules, &valid_tickets);
+        let field_mapping = assign_fields(&notes.rules, &valid_tickets).unwrap();
 
         assert_eq!(field_mapping["row"], 0);
         assert_eq!(field_mapping["class"], 1);
         assert_eq!(field_mapping["seat"], 2);
     }
+
+    #[test]
+    fn test_assignment_errors() {
+        // Both rules accept every value, so the positions could go either way.
+        let rules = parse_rules("a: 0-5 or 10-15\nb: 0-5 or 10-15");
+        assert_eq!(
+            assign_fields(&rules, &[vec![1, 2]]),
+            Err(FieldError::Ambiguous {
+                position: 0,
+                rules: (0, 1)
+            })
+        );
+
+        // Position 1 has no rule left once position 0 takes the only one fitting both.
+        let rules = parse_rules("a: 0-5 or 10-15\nb: 20-25 or 30-35");
+        assert_eq!(
+            assign_fields(&rules, &[vec![1, 2], vec![3, 4]]),
+            Err(FieldError::Unsolvable { position: 1 })
+        );
+        assert_eq!(
+            eliminations(&rules, &[vec![1, 2], vec![3, 4]], 1),
+            vec![Elimination {
+                rule: 1,
+                ticket: 0,
+                value: 2
+            }]
+        );
+        assert_eq!(
+            elimination_report(&rules, &[vec![1, 2], vec![3, 4]], 1),
+            "b ruled out at position 1 by 2 on ticket 0"
+        );
+    }
 }
@@ -1,69 +1,180 @@
 aoc::parts!(1, 2);
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    const WIDTH: usize = 12;
-    const COUNT: usize = 1000;
-
-    let gamma = input
-        .raw()
-        .lines()
-        .map(|l| usize::from_str_radix(l, 2).unwrap())
-        .fold(vec![0; WIDTH], |count, bits| {
-            count
-                .into_iter()
-                .enumerate()
-                .map(|(i, n)| n + ((bits & 1 << i) >> i))
-                .collect()
-        })
-        .into_iter()
-        .enumerate()
-        .map(|(i, b)| ((b >= COUNT / 2) as u32) << i)
-        .sum::<u32>();
+/// How many of the numbers in a diagnostic report have each bit set.
+#[derive(Debug, PartialEq, Eq)]
+struct BitCounts {
+    width: u32,
+    count: u64,
+    /// Indexed by bit position, least significant first.
+    ones: Vec<u64>,
+}
 
-    let epsilon = !gamma & ((1 << WIDTH) - 1); // Calculate epsilon separately
-    let result = gamma * epsilon; // Calculate the result
+/// Parses a line of the report, which must be 1 to 64 binary digits.
+fn parse_line(line: &str) -> (u64, u32) {
+    let width = line.len() as u32;
+    assert!(
+        (1..=64).contains(&width),
+        "{line:?} must be 1 to 64 bits wide"
+    );
+    let number = u64::from_str_radix(line, 2).unwrap_or_else(|_| panic!("{line:?} is not binary"));
+    (number, width)
+}
 
-    println!("{}", result); // Use the calculated result
-    let output = result; // Assign the calculated result
-    output
+/// Parses a line, checking it has the same width as the lines before it. `width` is set from the
+/// first line.
+fn parse_report_line(line: &str, width: &mut Option<u32>) -> u64 {
+    let (number, line_width) = parse_line(line);
+    assert_eq!(
+        *width.get_or_insert(line_width),
+        line_width,
+        "{line:?} differs in width"
+    );
+    number
 }
 
-fn part_2(input: aoc::Input) -> impl ToString {
-    const WIDTH: usize = 12;
-
-    let nums = input
-        .raw()
-        .lines()
-        .map(|l| u32::from_str_radix(l, 2).unwrap())
-        .collect::<Vec<_>>();
-
-    let mut oxy = nums.clone();
-    for i in (0..WIDTH).rev() {
-        let one = oxy.iter().filter(|n| *n & 1 << i > 0).count() >= (oxy.len() + 1) / 2;
-        oxy = oxy
-            .into_iter()
-            .filter(|n| (*n & 1 << i > 0) == one)
-            .collect();
-        if oxy.len() == 1 {
-            break;
+/// Counts the bits of every line in one pass, inferring the width from the lines themselves.
+fn count_bits<'a>(lines: impl IntoIterator<Item = &'a str>) -> BitCounts {
+    let mut width = None;
+    let mut count = 0;
+    let mut ones = Vec::new();
+    for line in lines {
+        let number = parse_report_line(line, &mut width);
+        ones.resize(width.unwrap() as usize, 0);
+        for (bit, ones) in ones.iter_mut().enumerate() {
+            *ones += number >> bit & 1;
         }
+        count += 1;
+    }
+    BitCounts {
+        width: width.unwrap_or(0),
+        count,
+        ones,
+    }
+}
+
+fn width_mask(width: u32) -> u64 {
+    u64::MAX >> (64 - width)
+}
+
+/// Gamma rate times epsilon rate: the most common bits, times the least common ones. `None` for
+/// an empty report.
+fn power_consumption(counts: &BitCounts) -> Option<u128> {
+    if counts.count == 0 {
+        return None;
     }
-    let oxy = oxy[0];
-
-    let mut co2 = nums;
-    for i in (0..WIDTH).rev() {
-        let one = co2.iter().filter(|n| *n & 1 << i > 0).count() >= (co2.len() + 1) / 2;
-        co2 = co2
-            .into_iter()
-            .filter(|n| (*n & 1 << i > 0) != one)
-            .collect();
-        if co2.len() == 1 {
+    let gamma = counts
+        .ones
+        .iter()
+        .enumerate()
+        .filter(|&(_, &ones)| ones * 2 >= counts.count)
+        .fold(0u64, |gamma, (bit, _)| gamma | 1 << bit);
+    let epsilon = !gamma & width_mask(counts.width);
+    Some(gamma as u128 * epsilon as u128)
+}
+
+#[derive(Debug, Clone, Copy)]
+enum BitCriteria {
+    /// Keep numbers with the most common bit, 1 on a tie.
+    MostCommon,
+    /// Keep numbers with the least common bit, 0 on a tie.
+    LeastCommon,
+}
+
+/// Filters the numbers bit by bit from the most significant one, keeping those that match the
+/// criteria, until one is left.
+fn filter_by_bit_criteria(mut numbers: Vec<u64>, width: u32, criteria: BitCriteria) -> Option<u64> {
+    for bit in (0..width).rev() {
+        if numbers.len() <= 1 {
             break;
         }
+        let ones = numbers.iter().filter(|&&n| n >> bit & 1 == 1).count();
+        let most_common = ones * 2 >= numbers.len();
+        let keep = match criteria {
+            BitCriteria::MostCommon => most_common,
+            BitCriteria::LeastCommon => !most_common,
+        };
+        numbers.retain(|&n| (n >> bit & 1 == 1) == keep);
     }
-    let co2 = co2[0];
+    match numbers[..] {
+        [rating] => Some(rating),
+        _ => None,
+    }
+}
 
-    println!("{}", oxy * co2);
-    let output = oxy * co2;
-    output
+/// Oxygen generator rating times CO2 scrubber rating.
+fn life_support_rating<'a>(lines: impl IntoIterator<Item = &'a str>) -> Option<u128> {
+    let mut width = None;
+    let numbers: Vec<u64> = lines
+        .into_iter()
+        .map(|line| parse_report_line(line, &mut width))
+        .collect();
+    let width = width?;
+
+    let oxygen = filter_by_bit_criteria(numbers.clone(), width, BitCriteria::MostCommon)?;
+    let co2 = filter_by_bit_criteria(numbers, width, BitCriteria::LeastCommon)?;
+    Some(oxygen as u128 * co2 as u128)
+}
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    power_consumption(&count_bits(input.raw().lines())).unwrap()
+}
+
+fn part_2(input: aoc::Input) -> impl ToString {
+    life_support_rating(input.raw().lines()).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str =
+        "00100\n11110\n10110\n10111\n10101\n01111\n00111\n11100\n10000\n11001\n00010\n01010";
+
+    #[test]
+    fn test_example() {
+        let counts = count_bits(EXAMPLE.lines());
+        assert_eq!(counts.width, 5);
+        assert_eq!(counts.count, 12);
+        assert_eq!(power_consumption(&counts), Some(198));
+        assert_eq!(life_support_rating(EXAMPLE.lines()), Some(230));
+
+        assert_eq!(power_consumption(&count_bits("".lines())), None);
+        assert_eq!(life_support_rating("".lines()), None);
+    }
+
+    #[test]
+    fn test_bit_criteria() {
+        let numbers: Vec<u64> = EXAMPLE.lines().map(|line| parse_line(line).0).collect();
+        assert_eq!(
+            filter_by_bit_criteria(numbers.clone(), 5, BitCriteria::MostCommon),
+            Some(0b10111)
+        );
+        assert_eq!(
+            filter_by_bit_criteria(numbers, 5, BitCriteria::LeastCommon),
+            Some(0b01010)
+        );
+        // Duplicates can never be told apart.
+        assert_eq!(
+            filter_by_bit_criteria(vec![3, 3], 2, BitCriteria::MostCommon),
+            None
+        );
+    }
+
+    #[test]
+    fn test_wide_numbers() {
+        let top_bit = format!("1{}", "0".repeat(63));
+        let lines = ["1".repeat(64), "0".repeat(64), top_bit.clone()];
+        let counts = count_bits(lines.iter().map(String::as_str));
+        // Gamma is the top bit alone, epsilon everything else.
+        assert_eq!(
+            power_consumption(&counts),
+            Some((1u128 << 63) * ((1u128 << 63) - 1))
+        );
+
+        let lines = ["1".repeat(64), top_bit, format!("0{}", "1".repeat(63))];
+        assert_eq!(
+            life_support_rating(lines.iter().map(String::as_str)),
+            Some(u64::MAX as u128 * ((1u128 << 63) - 1))
+        );
+    }
 }
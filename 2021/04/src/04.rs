@@ -2,103 +2,207 @@ use hashbrown::HashMap;
 
 aoc::parts!(1, 2);
 
-const ROW: u32 = 0b11111;
-const COL: u32 = 0b100001000010000100001;
-
-fn part_1(input: aoc::Input) -> impl ToString {
-    const ROW: u32 = 0b11111;
-    const COL: u32 = 0b100001000010000100001;
-
-    let (nums, boards) = input.raw().split_once("\n\n").unwrap();
-
-    let mut boards: Vec<(HashMap<u8, usize>, u32)> = boards
-        .split("\n\n")
-        .map(|b| {
-            (
-                b.split_ascii_whitespace()
-                    .enumerate()
-                    .map(|(i, n)| (n.parse().unwrap(), i))
-                    .collect(),
-                0,
-            )
-        })
-        .collect();
+struct Board {
+    size: usize,
+    /// Position of each number on the board, row by row.
+    positions: HashMap<u32, usize>,
+    /// Bit `i` is set once the number at position `i` has been called.
+    marked: u128,
+}
 
-    let (board, mark, num) = nums
-        .split(',')
-        .map(|n| n.parse::<u8>().unwrap())
-        .find_map(|n| {
-            boards.iter_mut().find_map(|(b, m)| {
-                b.get(&n)
-                    .map(|i| *m |= 1 << *i)
-                    .filter(|_| (0..5).any(|i| *m >> i & COL == COL || *m >> (i * 5) & ROW == ROW))
-                    .map(|_| (b.clone(), *m, n))
+impl Board {
+    fn parse(board: &str) -> Board {
+        let rows: Vec<Vec<u32>> = board
+            .lines()
+            .map(|row| {
+                row.split_ascii_whitespace()
+                    .map(|n| n.parse().unwrap())
+                    .collect()
             })
-        })
-        .unwrap();
-
-    let result = board
-        .iter() // Iterate over the board without consuming it
-        .map(|(n, i)| (mark >> i & 1 ^ 1) * (*n) as u32 * num as u32)
-        .sum::<u32>();
-
-    println!("{}", result);
-
-    result // Return the calculated result
+            .collect();
+        let size = rows.len();
+        assert!(
+            size * size <= 128,
+            "boards larger than 11x11 are not supported"
+        );
+        assert!(
+            rows.iter().all(|row| row.len() == size),
+            "board is not square:\n{board}"
+        );
+        Board {
+            size,
+            positions: rows
+                .into_iter()
+                .flatten()
+                .enumerate()
+                .map(|(i, n)| (n, i))
+                .collect(),
+            marked: 0,
+        }
+    }
+
+    /// Marks `number`, returning whether that completed a row or column.
+    fn mark(&mut self, number: u32, lines: &[u128]) -> bool {
+        let Some(&position) = self.positions.get(&number) else {
+            return false;
+        };
+        self.marked |= 1 << position;
+        lines.iter().any(|&line| line & !self.marked == 0)
+    }
+
+    fn unmarked_sum(&self) -> u64 {
+        self.positions
+            .iter()
+            .filter(|(_, &position)| self.marked >> position & 1 == 0)
+            .map(|(&number, _)| number as u64)
+            .sum()
+    }
 }
 
-fn part_2(input: aoc::Input) -> impl ToString {
-    let (nums, boards) = input.raw().split_once("\n\n").unwrap();
-
-    let mut boards: Vec<(HashMap<u8, usize>, u32)> = boards
-        .split("\n\n")
-        .map(|b| {
-            (
-                b.split_ascii_whitespace()
-                    .enumerate()
-                    .map(|(i, n)| (n.parse().unwrap(), i))
-                    .collect(),
-                0,
-            )
-        })
-        .collect();
+/// Masks of every row and column on a `size` by `size` board.
+fn line_masks(size: usize) -> Vec<u128> {
+    let row = (1u128 << size) - 1;
+    let column = (0..size).fold(0u128, |column, i| column | 1 << (i * size));
+    let rows = (0..size).map(|i| row << (i * size));
+    let columns = (0..size).map(|i| column << i);
+    rows.chain(columns).collect()
+}
 
-    let nums = nums
+fn parse_game(input: &str) -> (Vec<u32>, Vec<Board>) {
+    let (numbers, boards) = input.split_once("\n\n").unwrap();
+    let numbers = numbers
         .split(',')
-        .map(|n| n.parse::<u8>().unwrap())
-        .collect::<Vec<_>>(); // Specify type here
-
-    let (board, mark, num) = 'outer: loop {
-        for n in nums.iter().copied() {
-            let mut winning_boards = Vec::new();
-
-            for i in 0..boards.len() {
-                let (b, m) = &mut boards[i];
-                if let Some(j) = b.get(&n) {
-                    *m |= 1 << *j;
-                    if (0..5).any(|i| *m >> i & COL == COL || *m >> (i * 5) & ROW == ROW) {
-                        winning_boards.push(i);
-                    }
-                }
-            }
+        .map(|n| n.trim().parse().unwrap())
+        .collect();
+    let boards = boards.trim().split("\n\n").map(Board::parse).collect();
+    (numbers, boards)
+}
 
-            if boards.len() == 1 && !winning_boards.is_empty() {
-                let (board, mark) = boards.remove(0);
-                break 'outer (board, mark, n);
-            }
+/// When a board won, and with what score.
+#[derive(Debug, PartialEq, Eq)]
+struct Win {
+    board: usize,
+    /// Index of the winning number in the draw.
+    turn: usize,
+    score: u64,
+}
 
-            for i in winning_boards.into_iter().rev() {
-                boards.remove(i);
+/// Plays every number, returning the boards in the order they win. Boards completing on the same
+/// number are ranked by their order in the input; boards that never win are left out.
+fn play(numbers: &[u32], boards: &mut [Board]) -> Vec<Win> {
+    let sizes: HashMap<usize, Vec<u128>> = boards
+        .iter()
+        .map(|board| (board.size, line_masks(board.size)))
+        .collect();
+    let mut ranking = Vec::new();
+    let mut won = vec![false; boards.len()];
+
+    for (turn, &number) in numbers.iter().enumerate() {
+        for (i, board) in boards.iter_mut().enumerate() {
+            if !won[i] && board.mark(number, &sizes[&board.size]) {
+                won[i] = true;
+                ranking.push(Win {
+                    board: i,
+                    turn,
+                    score: board.unmarked_sum() * number as u64,
+                });
             }
         }
-    };
+        if ranking.len() == boards.len() {
+            break;
+        }
+    }
 
-    let result = board
-        .iter()
-        .map(|(n, i)| (mark >> i & 1 ^ 1) * (*n) as u32 * num as u32)
-        .sum::<u32>();
+    ranking
+}
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    let (numbers, mut boards) = parse_game(input.raw());
+    play(&numbers, &mut boards)[0].score
+}
 
-    println!("{}", result);
+fn part_2(input: aoc::Input) -> impl ToString {
+    let (numbers, mut boards) = parse_game(input.raw());
+    play(&numbers, &mut boards).last().unwrap().score
+}
 
-    result
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "7,4,9,5,11,17,23,2,0,14,21,24,10,16,13,6,15,25,12,22,18,20,8,19,3,26,1
+
+22 13 17 11  0
+ 8  2 23  4 24
+21  9 14 16  7
+ 6 10  3 18  5
+ 1 12 20 15 19
+
+ 3 15  0  2 22
+ 9 18 13 17  5
+19  8  7 25 23
+20 11 10 24  4
+14 21 16 12  6
+
+14 21 17 24  4
+10 16 15  9 19
+18  8 23 26 20
+22 11 13  6  5
+ 2  0 12  3  7
+";
+
+    #[test]
+    fn test_ranking() {
+        let (numbers, mut boards) = parse_game(EXAMPLE);
+        assert_eq!(
+            play(&numbers, &mut boards),
+            [
+                Win {
+                    board: 2,
+                    turn: 11,
+                    score: 4512
+                },
+                Win {
+                    board: 0,
+                    turn: 13,
+                    score: 2192
+                },
+                Win {
+                    board: 1,
+                    turn: 14,
+                    score: 1924
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_line_masks() {
+        assert_eq!(
+            line_masks(5),
+            [
+                0b11111,
+                0b11111 << 5,
+                0b11111 << 10,
+                0b11111 << 15,
+                0b11111 << 20,
+                0b100001000010000100001,
+                0b100001000010000100001 << 1,
+                0b100001000010000100001 << 2,
+                0b100001000010000100001 << 3,
+                0b100001000010000100001 << 4,
+            ]
+        );
+
+        // A 3x3 board wins on its middle column, a 2x2 board never completes a line.
+        let (numbers, mut boards) = parse_game("2,5,8,1\n\n1 2 3\n4 5 6\n7 8 9\n\n1 3\n4 6");
+        assert_eq!(
+            play(&numbers, &mut boards),
+            [Win {
+                board: 0,
+                turn: 2,
+                score: (1 + 3 + 4 + 6 + 7 + 9) * 8
+            }]
+        );
+    }
 }
@@ -1,44 +1,226 @@
-use itertools::Itertools;
+use std::collections::HashSet;
 
-aoc::parts!(1);
+aoc::parts!(1, 2);
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let mut map = vec![0u8; 1000 * 1000];
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: (i64, i64),
+    end: (i64, i64),
+}
+
+impl Segment {
+    fn parse(line: &str) -> Segment {
+        let point = |s: &str| {
+            let (x, y) = s.trim().split_once(',').unwrap();
+            (x.parse().unwrap(), y.parse().unwrap())
+        };
+        let (start, end) = line.split_once(" -> ").unwrap();
+        Segment {
+            start: point(start),
+            end: point(end),
+        }
+    }
+
+    /// Unit step from `start` towards `end`.
+    fn direction(&self) -> (i64, i64) {
+        (
+            (self.end.0 - self.start.0).signum(),
+            (self.end.1 - self.start.1).signum(),
+        )
+    }
+
+    /// Number of steps from `start` to `end`.
+    fn length(&self) -> i64 {
+        (self.end.0 - self.start.0)
+            .abs()
+            .max((self.end.1 - self.start.1).abs())
+    }
+
+    fn is_diagonal(&self) -> bool {
+        self.start.0 != self.end.0 && self.start.1 != self.end.1
+    }
+
+    fn point_at(&self, step: i64) -> (i64, i64) {
+        let (dx, dy) = self.direction();
+        (self.start.0 + dx * step, self.start.1 + dy * step)
+    }
+
+    /// Whether `point` is one of the segment's lattice points.
+    fn contains(&self, point: (i64, i64)) -> bool {
+        let (dx, dy) = self.direction();
+        let offset = (point.0 - self.start.0, point.1 - self.start.1);
+        let step = offset.0.abs().max(offset.1.abs());
+        offset == (dx * step, dy * step) && step <= self.length()
+    }
+
+    fn points(&self) -> impl Iterator<Item = (i64, i64)> + '_ {
+        (0..=self.length()).map(|step| self.point_at(step))
+    }
+}
+
+fn parse_segments(input: &str, include_diagonals: bool) -> Vec<Segment> {
+    let segments = input.lines().map(Segment::parse);
+    segments
+        .filter(|segment| include_diagonals || !segment.is_diagonal())
+        .collect()
+}
+
+/// Largest bounding box, in cells, that `count_overlaps` will draw on a map.
+const MAX_MAP_CELLS: i64 = 1 << 26;
+
+/// Number of points covered by at least two segments.
+///
+/// Draws the segments on a map sized to their bounding box, falling back to pairwise
+/// intersection when the coordinates are spread too far apart for that.
+fn count_overlaps(segments: &[Segment]) -> usize {
+    let xs = segments.iter().flat_map(|s| [s.start.0, s.end.0]);
+    let ys = segments.iter().flat_map(|s| [s.start.1, s.end.1]);
+    let (Some(min_x), Some(max_x)) = (xs.clone().min(), xs.max()) else {
+        return 0;
+    };
+    let (min_y, max_y) = (ys.clone().min().unwrap(), ys.max().unwrap());
+    let (width, height) = (max_x - min_x + 1, max_y - min_y + 1);
+    if width.saturating_mul(height) > MAX_MAP_CELLS {
+        return count_overlaps_by_intersection(segments);
+    }
+    let width = width as usize;
+    let height = height as usize;
+
+    // Counts saturate, we only care whether a point was covered more than once.
+    let mut map = vec![0u8; width * height];
     let mut overlaps = 0;
+    for segment in segments {
+        for (x, y) in segment.points() {
+            let index = (x - min_x) as usize + (y - min_y) as usize * width;
+            overlaps += (map[index] == 1) as usize;
+            map[index] = map[index].saturating_add(1);
+        }
+    }
+    overlaps
+}
 
-    for line in input.lines() {
-        let (mut p1, mut p2) = line
-            .split_once(" -> ")
-            .unwrap()
-            .map(|s| {
-                s.split(',')
-                    .map(str::parse::<usize>)
-                    .collect::<Result<Vec<_>, _>>()
-                    .unwrap()
-            })
-            .collect::<Vec<_>>();
-
-        for ((x1, y1), (x2, y2)) in itertools::tuple_windows(&[(p1[0], p1[1]), (p2[0], p2[1])]) {
-            if x1 == x2 {
-                let (y1, y2) = (y1.min(y2), y1.max(y2));
-                for y in y1..=y2 {
-                    let index = (x1 + y * 1000) as usize;
-                    overlaps += (map[index] == 1) as u32;
-                    map[index] += 1;
-                }
-            } else if y1 == y2 {
-                let (x1, x2) = (x1.min(x2), x1.max(x2));
-                for x in x1..=x2 {
-                    let index = (x + y1 * 1000) as usize;
-                    overlaps += (map[index] == 1) as u32;
-                    map[index] += 1;
-                }
+fn cross(a: (i64, i64), b: (i64, i64)) -> i64 {
+    a.0 * b.1 - a.1 * b.0
+}
+
+/// Lattice points shared by two segments.
+fn intersections(a: &Segment, b: &Segment) -> Vec<(i64, i64)> {
+    // A single point has no direction to intersect along, so check it against the other segment.
+    if a.length() == 0 || b.length() == 0 {
+        let (point, segment) = if a.length() == 0 { (a, b) } else { (b, a) };
+        return segment
+            .contains(point.start)
+            .then_some(point.start)
+            .into_iter()
+            .collect();
+    }
+
+    let (da, db) = (a.direction(), b.direction());
+    let offset = (b.start.0 - a.start.0, b.start.1 - a.start.1);
+    let denominator = cross(da, db);
+
+    if denominator == 0 {
+        // Parallel: only collinear segments share points, along a common range of `a`'s steps.
+        if cross(offset, da) != 0 {
+            return Vec::new();
+        }
+        let step_of = |p: (i64, i64)| {
+            if da.0 != 0 {
+                (p.0 - a.start.0) * da.0
+            } else {
+                (p.1 - a.start.1) * da.1
             }
+        };
+        let (b_first, b_last) = (step_of(b.start), step_of(b.end));
+        let first = b_first.min(b_last).max(0);
+        let last = b_first.max(b_last).min(a.length());
+        return (first..=last).map(|step| a.point_at(step)).collect();
+    }
+
+    // Crossing lines: the intersection must fall on whole steps of both segments.
+    let step_a = cross(offset, db);
+    let step_b = cross(offset, da);
+    if step_a % denominator != 0 || step_b % denominator != 0 {
+        return Vec::new();
+    }
+    let (step_a, step_b) = (step_a / denominator, step_b / denominator);
+    if (0..=a.length()).contains(&step_a) && (0..=b.length()).contains(&step_b) {
+        vec![a.point_at(step_a)]
+    } else {
+        Vec::new()
+    }
+}
+
+/// Intersects segments pairwise instead of drawing them, so memory use does not depend on how
+/// large the coordinates are.
+fn count_overlaps_by_intersection(segments: &[Segment]) -> usize {
+    let mut overlaps = HashSet::new();
+    for (i, a) in segments.iter().enumerate() {
+        for b in &segments[i + 1..] {
+            overlaps.extend(intersections(a, b));
         }
     }
+    overlaps.len()
+}
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    count_overlaps(&parse_segments(input.raw(), false))
+}
+
+fn part_2(input: aoc::Input) -> impl ToString {
+    count_overlaps(&parse_segments(input.raw(), true))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "0,9 -> 5,9
+8,0 -> 0,8
+9,4 -> 3,4
+2,2 -> 2,1
+7,0 -> 7,4
+6,4 -> 2,0
+0,9 -> 2,9
+3,4 -> 1,4
+0,0 -> 8,8
+5,5 -> 8,2";
+
+    #[test]
+    fn test_count_overlaps() {
+        assert_eq!(count_overlaps(&parse_segments(EXAMPLE, false)), 5);
+        assert_eq!(count_overlaps(&parse_segments(EXAMPLE, true)), 12);
+    }
+
+    #[test]
+    fn test_count_overlaps_by_intersection() {
+        assert_eq!(
+            count_overlaps_by_intersection(&parse_segments(EXAMPLE, false)),
+            5
+        );
+        assert_eq!(
+            count_overlaps_by_intersection(&parse_segments(EXAMPLE, true)),
+            12
+        );
+
+        // A single point only overlaps segments it lies on.
+        let input = "5,5 -> 5,5\n0,0 -> 0,3";
+        assert_eq!(count_overlaps(&parse_segments(input, true)), 0);
+        assert_eq!(
+            count_overlaps_by_intersection(&parse_segments(input, true)),
+            0
+        );
+        let input = "0,2 -> 0,2\n0,0 -> 0,3\n0,2 -> 0,2";
+        assert_eq!(count_overlaps(&parse_segments(input, true)), 1);
+        assert_eq!(
+            count_overlaps_by_intersection(&parse_segments(input, true)),
+            1
+        );
+    }
 
-    overlaps.to_string()
+    #[test]
+    fn test_large_coordinates() {
+        let input = "1000000,0 -> 1000000,2000000\n0,1000000 -> 2000000,1000000\n999999,999999 -> 1000001,1000001";
+        assert_eq!(count_overlaps(&parse_segments(input, true)), 1);
+    }
 }
-// fn part_2(input: aoc::Input) -> impl ToString {
-//     0
-// }
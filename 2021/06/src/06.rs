@@ -1,25 +1,196 @@
 aoc::parts!(1, 2);
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let mut map = input.raw().split(',').fold([0; 9], |mut map, n| {
-        map[n.parse::<usize>().unwrap()] += 1;
-        map
-    });
+/// Timer values a fish restarts at after spawning, and that a new fish starts with.
+#[derive(Debug, Clone, Copy)]
+struct Timers {
+    reset: usize,
+    new_fish: usize,
+}
+
+const DEFAULT_TIMERS: Timers = Timers {
+    reset: 6,
+    new_fish: 8,
+};
+
+impl Timers {
+    fn buckets(&self) -> usize {
+        self.reset.max(self.new_fish) + 1
+    }
+}
+
+type Matrix = Vec<Vec<u128>>;
+
+/// Number of fish with each timer value.
+fn parse_counts(input: &str, timers: Timers) -> Vec<u128> {
+    let mut counts = vec![0; timers.buckets()];
+    for n in input.trim().split(',') {
+        let timer: usize = n.trim().parse().unwrap();
+        assert!(timer < counts.len(), "timer {timer} is out of range");
+        counts[timer] += 1;
+    }
+    counts
+}
+
+/// One day as a linear map on the timer counts: `next = matrix * today`.
+fn transition_matrix(timers: Timers) -> Matrix {
+    let n = timers.buckets();
+    let mut matrix = vec![vec![0; n]; n];
+    for timer in 1..n {
+        matrix[timer - 1][timer] = 1;
+    }
+    // Fish at 0 restart at `reset` and spawn a fish at `new_fish`.
+    matrix[timers.reset][0] += 1;
+    matrix[timers.new_fish][0] += 1;
+    matrix
+}
 
-    (1..80).for_each(|day| map[(day + 7) % 9] += map[day % 9]);
+/// Product of two matrices, reduced by `modulus` if there is one, or `None` on overflow.
+fn multiply(a: &Matrix, b: &Matrix, modulus: Option<u64>) -> Option<Matrix> {
+    let n = a.len();
+    let mut product = vec![vec![0; b[0].len()]; n];
+    for i in 0..n {
+        for (k, row) in b.iter().enumerate() {
+            for (j, &value) in row.iter().enumerate() {
+                product[i][j] = match modulus {
+                    // Both factors are below 2^64, so this cannot overflow.
+                    Some(m) => (product[i][j] + a[i][k] * value % m as u128) % m as u128,
+                    None => product[i][j].checked_add(a[i][k].checked_mul(value)?)?,
+                };
+            }
+        }
+    }
+    Some(product)
+}
+
+/// `matrix` raised to `exponent` by repeated squaring.
+fn power(matrix: &Matrix, mut exponent: u64, modulus: Option<u64>) -> Option<Matrix> {
+    let n = matrix.len();
+    let mut result: Matrix = (0..n)
+        .map(|i| (0..n).map(|j| (i == j) as u128).collect())
+        .collect();
+    let mut base = matrix.clone();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = multiply(&base, &result, modulus)?;
+        }
+        exponent >>= 1;
+        // Skipping the final squaring keeps exact results from overflowing needlessly.
+        if exponent > 0 {
+            base = multiply(&base, &base, modulus)?;
+        }
+    }
+    Some(result)
+}
+
+/// Number of fish after `days`, modulo `modulus` if given. Without one, `None` if the count
+/// does not fit in a `u128`.
+fn population(counts: &[u128], days: u64, timers: Timers, modulus: Option<u64>) -> Option<u128> {
+    let counts: Matrix = counts
+        .iter()
+        .map(|&count| vec![modulus.map_or(count, |m| count % m as u128)])
+        .collect();
+    let after = multiply(
+        &power(&transition_matrix(timers), days, modulus)?,
+        &counts,
+        modulus,
+    )?;
+    after.iter().try_fold(0u128, |total, count| match modulus {
+        Some(m) => Some((total + count[0]) % m as u128),
+        None => total.checked_add(count[0]),
+    })
+}
 
-    let result = map.iter().sum::<usize>();
-    result
+fn part_1(input: aoc::Input) -> impl ToString {
+    let counts = parse_counts(input.raw(), DEFAULT_TIMERS);
+    population(&counts, 80, DEFAULT_TIMERS, None).unwrap()
 }
 
 fn part_2(input: aoc::Input) -> impl ToString {
-    let mut map = input.raw().split(',').fold([0; 9], |mut map, n| {
-        map[n.parse::<usize>().unwrap()] += 1;
-        map
-    });
+    let counts = parse_counts(input.raw(), DEFAULT_TIMERS);
+    population(&counts, 256, DEFAULT_TIMERS, None).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Day by day, as the puzzle describes it.
+    fn simulate(timers: &[usize], days: u64, config: Timers) -> usize {
+        let mut timers = timers.to_vec();
+        for _ in 0..days {
+            let spawned = timers.iter().filter(|&&t| t == 0).count();
+            for timer in timers.iter_mut() {
+                *timer = if *timer == 0 {
+                    config.reset
+                } else {
+                    *timer - 1
+                };
+            }
+            timers.extend(std::iter::repeat_n(config.new_fish, spawned));
+        }
+        timers.len()
+    }
+
+    #[test]
+    fn test_example() {
+        let counts = parse_counts("3,4,3,1,2\n", DEFAULT_TIMERS);
+        assert_eq!(population(&counts, 18, DEFAULT_TIMERS, None), Some(26));
+        assert_eq!(population(&counts, 80, DEFAULT_TIMERS, None), Some(5934));
+        assert_eq!(
+            population(&counts, 256, DEFAULT_TIMERS, None),
+            Some(26984457539)
+        );
+        assert_eq!(
+            population(&counts, 256, DEFAULT_TIMERS, Some(1_000_000_007)),
+            Some(26984457539 % 1_000_000_007)
+        );
+    }
 
-    (1..256).for_each(|day| map[(day + 7) % 9] += map[day % 9]);
+    #[test]
+    fn test_huge_days() {
+        let counts = parse_counts("3,4,3,1,2", DEFAULT_TIMERS);
+        assert_eq!(
+            population(&counts, 1_000_000_000_000_000_000, DEFAULT_TIMERS, None),
+            None
+        );
+        let modular = population(
+            &counts,
+            1_000_000_000_000_000_000,
+            DEFAULT_TIMERS,
+            Some(1 << 61),
+        );
+        assert!(modular.is_some_and(|count| count < 1 << 61));
+    }
 
-    let result = map.iter().sum::<usize>();
-    result
+    #[test]
+    fn test_custom_timers() {
+        for timers in [
+            Timers {
+                reset: 2,
+                new_fish: 4,
+            },
+            Timers {
+                reset: 5,
+                new_fish: 1,
+            },
+            Timers {
+                reset: 0,
+                new_fish: 0,
+            },
+        ] {
+            let initial = [0, 1, 1, 2];
+            let initial: Vec<usize> = initial
+                .into_iter()
+                .filter(|&t| t < timers.buckets())
+                .collect();
+            let input: Vec<_> = initial.iter().map(|t| t.to_string()).collect();
+            let counts = parse_counts(&input.join(","), timers);
+            for days in [0, 1, 7, 20] {
+                assert_eq!(
+                    population(&counts, days, timers, None),
+                    Some(simulate(&initial, days, timers) as u128)
+                );
+            }
+        }
+    }
 }
@@ -1,38 +1,205 @@
 aoc::parts!(1, 2);
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let mut subs = input
-        .raw()
-        .split(',')
-        .map(|n| n.parse().unwrap())
-        .collect::<Vec<i32>>();
+/// Fuel a crab burns to move a given distance. Every model must be convex in the distance, which
+/// makes the total fuel convex in the target position.
+#[allow(dead_code)] // The quadratic and custom models are only exercised by the tests.
+enum CostModel<'a> {
+    /// One unit per step.
+    Linear,
+    /// One more unit for each step than the last: `d * (d + 1) / 2`.
+    Triangular,
+    /// `d * d`.
+    Quadratic,
+    Custom(&'a dyn Fn(u64) -> u64),
+}
 
-    let mid = subs.len() / 2;
-    let med = *subs.select_nth_unstable(mid).1;
+impl CostModel<'_> {
+    fn cost(&self, distance: u64) -> u64 {
+        match self {
+            CostModel::Linear => distance,
+            CostModel::Triangular => distance * (distance + 1) / 2,
+            CostModel::Quadratic => distance * distance,
+            CostModel::Custom(cost) => cost(distance),
+        }
+    }
 
-    let result = subs.iter().map(|n| (n - med).abs()).sum::<i32>();
-    result
+    /// `(square, linear, divisor)` when the cost is `(square * d^2 + linear * d) / divisor`.
+    fn coefficients(&self) -> Option<(i128, i128, i128)> {
+        match self {
+            CostModel::Linear => Some((0, 1, 1)),
+            CostModel::Triangular => Some((1, 1, 2)),
+            CostModel::Quadratic => Some((1, 0, 1)),
+            CostModel::Custom(_) => None,
+        }
+    }
 }
 
-fn part_2(input: aoc::Input) -> impl ToString {
-    let subs = input
-        .raw()
+/// Where the crabs line up, and the fuel it takes them.
+#[derive(Debug, PartialEq, Eq)]
+struct Alignment {
+    position: i64,
+    cost: u64,
+}
+
+fn parse_positions(input: &str) -> Vec<i64> {
+    input
+        .trim()
         .split(',')
-        .map(|n| n.parse().unwrap())
-        .collect::<Vec<_>>();
-
-    let result = (subs.iter().sum::<i32>() / subs.len() as i32..)
-        .take(2)
-        .map(|t| {
-            subs.iter()
-                .map(|n| {
-                    let d = (n - t).abs();
-                    d * (d + 1) / 2
-                })
-                .sum::<i32>()
+        .map(|n| n.trim().parse().unwrap())
+        .collect()
+}
+
+fn total_cost(positions: &[i64], target: i64, model: &CostModel) -> u64 {
+    positions
+        .iter()
+        .map(|&p| model.cost(p.abs_diff(target)))
+        .sum()
+}
+
+/// Cheapest alignment for any convex cost, by ternary search over the crabs' range.
+///
+/// When several positions tie, any of them may be returned.
+fn align_by_ternary_search(positions: &[i64], model: &CostModel) -> Option<Alignment> {
+    let mut lo = *positions.iter().min()?;
+    let mut hi = *positions.iter().max()?;
+    let cost = |target| total_cost(positions, target, model);
+
+    while hi - lo > 2 {
+        let m1 = lo + (hi - lo) / 3;
+        let m2 = hi - (hi - lo) / 3;
+        match cost(m1).cmp(&cost(m2)) {
+            std::cmp::Ordering::Less => hi = m2 - 1,
+            std::cmp::Ordering::Greater => lo = m1 + 1,
+            // By convexity the minimum is between the two.
+            std::cmp::Ordering::Equal => (lo, hi) = (m1, m2),
+        }
+    }
+
+    (lo..=hi)
+        .map(|position| Alignment {
+            position,
+            cost: cost(position),
         })
-        .min()
-        .unwrap();
+        .min_by_key(|alignment| alignment.cost)
+}
+
+/// Cheapest alignment for a cost that is a quadratic in the distance, trying every position in
+/// constant time each from prefix sums of the sorted positions and their squares.
+///
+/// When several positions tie, the leftmost is returned.
+fn align_by_prefix_sums(
+    positions: &[i64],
+    (square, linear, divisor): (i128, i128, i128),
+) -> Option<Alignment> {
+    let mut sorted = positions.to_vec();
+    sorted.sort_unstable();
+    let (&min, &max) = (sorted.first()?, sorted.last()?);
+
+    let n = sorted.len() as i128;
+    let mut prefix = vec![0i128; sorted.len() + 1];
+    for (i, &p) in sorted.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + p as i128;
+    }
+    let sum = prefix[sorted.len()];
+    let sum_of_squares: i128 = sorted.iter().map(|&p| p as i128 * p as i128).sum();
+
+    let mut left = 0;
+    (min..=max)
+        .map(|position| {
+            while left < sorted.len() && sorted[left] <= position {
+                left += 1;
+            }
+            let t = position as i128;
+            let k = left as i128;
+            // Crabs at or left of the target move right, the others move left.
+            let distances = (k * t - prefix[left]) + (sum - prefix[left] - (n - k) * t);
+            let squares = sum_of_squares - 2 * t * sum + n * t * t;
+            Alignment {
+                position,
+                cost: ((square * squares + linear * distances) / divisor) as u64,
+            }
+        })
+        .min_by_key(|alignment| alignment.cost)
+}
+
+/// Cheapest alignment, from prefix sums when the model allows it.
+fn align(positions: &[i64], model: &CostModel) -> Option<Alignment> {
+    match model.coefficients() {
+        Some(coefficients) => align_by_prefix_sums(positions, coefficients),
+        None => align_by_ternary_search(positions, model),
+    }
+}
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    let positions = parse_positions(input.raw());
+    align(&positions, &CostModel::Linear).unwrap().cost
+}
+
+fn part_2(input: aoc::Input) -> impl ToString {
+    let positions = parse_positions(input.raw());
+    align(&positions, &CostModel::Triangular).unwrap().cost
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "16,1,2,0,4,2,7,1,2,14\n";
+
+    #[test]
+    fn test_example() {
+        let positions = parse_positions(EXAMPLE);
+        assert_eq!(
+            align(&positions, &CostModel::Linear),
+            Some(Alignment {
+                position: 2,
+                cost: 37
+            })
+        );
+        assert_eq!(
+            align(&positions, &CostModel::Triangular),
+            Some(Alignment {
+                position: 5,
+                cost: 168
+            })
+        );
+        assert_eq!(align(&[], &CostModel::Linear), None);
+    }
+
+    #[test]
+    fn test_methods_agree() {
+        let positions = parse_positions(EXAMPLE);
+        for model in [
+            CostModel::Linear,
+            CostModel::Triangular,
+            CostModel::Quadratic,
+        ] {
+            let by_prefix_sums = align(&positions, &model).unwrap();
+            let by_search = align_by_ternary_search(&positions, &model).unwrap();
+            assert_eq!(by_prefix_sums.cost, by_search.cost);
+            assert_eq!(
+                total_cost(&positions, by_search.position, &model),
+                by_search.cost
+            );
+        }
+
+        // Quadratic cost is least at the mean, 4.9 here.
+        assert_eq!(
+            align(&positions, &CostModel::Quadratic).unwrap().position,
+            5
+        );
+    }
 
-    result
+    #[test]
+    fn test_custom_model() {
+        let positions = parse_positions(EXAMPLE);
+        // Free for the first three steps, then three units a step.
+        let cost = |distance: u64| distance.saturating_sub(3) * 3;
+        let alignment = align(&positions, &CostModel::Custom(&cost)).unwrap();
+        let best = (0..=16)
+            .map(|target| total_cost(&positions, target, &CostModel::Custom(&cost)))
+            .min()
+            .unwrap();
+        assert_eq!(alignment.cost, best);
+    }
 }
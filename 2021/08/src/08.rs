@@ -1,52 +1,270 @@
 aoc::parts!(1, 2);
 
+/// Which segments, as bits from `a` upwards, light up for each digit of a display.
+struct Layout {
+    segments: u32,
+    /// Indexed by digit value.
+    digits: Vec<u32>,
+}
+
+impl Layout {
+    /// The usual seven-segment display.
+    fn seven_segment() -> Layout {
+        let digits = [
+            "abcefg", "cf", "acdeg", "acdfg", "bcdf", "abdfg", "abdefg", "acf", "abcdefg", "abcdfg",
+        ];
+        Layout {
+            segments: 7,
+            digits: digits.iter().map(|d| parse_pattern(d, 7)).collect(),
+        }
+    }
+
+    /// Segment counts that only one digit has.
+    fn unique_lengths(&self) -> Vec<u32> {
+        let lengths: Vec<_> = self.digits.iter().map(|d| d.count_ones()).collect();
+        lengths
+            .iter()
+            .filter(|&&len| lengths.iter().filter(|&&other| other == len).count() == 1)
+            .copied()
+            .collect()
+    }
+}
+
+fn parse_pattern(pattern: &str, segments: u32) -> u32 {
+    pattern.bytes().fold(0, |mask, b| {
+        let wire = b.checked_sub(b'a').map(u32::from);
+        match wire {
+            Some(wire) if wire < segments => mask | 1 << wire,
+            _ => panic!("unknown wire {:?}", b as char),
+        }
+    })
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum WiringError {
+    /// No way of connecting the wires produces the layout's digits.
+    NoWiring,
+    /// More than one way does, so the output cannot be trusted.
+    Ambiguous,
+}
+
+/// A display's wiring and what its output reads.
+#[derive(Debug, PartialEq, Eq)]
+struct Decoded {
+    /// The segment each wire is connected to.
+    wiring: Vec<u32>,
+    output: Vec<usize>,
+}
+
+impl Decoded {
+    fn value(&self, base: usize) -> u64 {
+        self.output
+            .iter()
+            .fold(0, |value, &digit| value * base as u64 + digit as u64)
+    }
+}
+
+/// Each wire's signature: how many patterns of each length it is part of.
+///
+/// Connecting a wire to a segment keeps the signature, so a wire can only go to a segment with
+/// the same signature among the layout's digits.
+fn signatures(patterns: &[u32], segments: u32) -> Vec<Vec<usize>> {
+    (0..segments)
+        .map(|bit| {
+            let mut signature = vec![0; segments as usize + 1];
+            for pattern in patterns.iter().filter(|&&p| p >> bit & 1 == 1) {
+                signature[pattern.count_ones() as usize] += 1;
+            }
+            signature
+        })
+        .collect()
+}
+
+fn rewire(pattern: u32, wiring: &[u32]) -> u32 {
+    wiring
+        .iter()
+        .enumerate()
+        .filter(|&(wire, _)| pattern >> wire & 1 == 1)
+        .fold(0, |mask, (_, &segment)| mask | 1 << segment)
+}
+
+/// Tries every wiring allowed by `candidates`, keeping the first and counting up to two.
+fn search(
+    candidates: &[u32],
+    wiring: &mut Vec<u32>,
+    used: u32,
+    patterns: &[u32],
+    digits: &[u32],
+    found: &mut Vec<Vec<u32>>,
+) {
+    if found.len() > 1 {
+        return;
+    }
+    let wire = wiring.len();
+    if wire == candidates.len() {
+        let mut rewired: Vec<_> = patterns.iter().map(|&p| rewire(p, wiring)).collect();
+        rewired.sort_unstable();
+        if rewired == digits {
+            found.push(wiring.clone());
+        }
+        return;
+    }
+
+    let mut remaining = candidates[wire] & !used;
+    while remaining != 0 {
+        let segment = remaining.trailing_zeros();
+        remaining &= remaining - 1;
+        wiring.push(segment);
+        search(
+            candidates,
+            wiring,
+            used | 1 << segment,
+            patterns,
+            digits,
+            found,
+        );
+        wiring.pop();
+    }
+}
+
+/// Works out the wiring from the ten unique patterns and reads the output with it.
+fn decode(layout: &Layout, patterns: &[&str], output: &[&str]) -> Result<Decoded, WiringError> {
+    let patterns: Vec<_> = patterns
+        .iter()
+        .map(|p| parse_pattern(p, layout.segments))
+        .collect();
+    let mut digits = layout.digits.clone();
+    digits.sort_unstable();
+
+    let wire_signatures = signatures(&patterns, layout.segments);
+    let segment_signatures = signatures(&layout.digits, layout.segments);
+    let candidates: Vec<u32> = wire_signatures
+        .iter()
+        .map(|wire| {
+            (0..layout.segments)
+                .filter(|&segment| segment_signatures[segment as usize] == *wire)
+                .fold(0, |set, segment| set | 1 << segment)
+        })
+        .collect();
+
+    let mut found = Vec::new();
+    search(
+        &candidates,
+        &mut Vec::new(),
+        0,
+        &patterns,
+        &digits,
+        &mut found,
+    );
+    let wiring = match &mut found[..] {
+        [] => return Err(WiringError::NoWiring),
+        [wiring] => std::mem::take(wiring),
+        _ => return Err(WiringError::Ambiguous),
+    };
+
+    let output = output
+        .iter()
+        .map(|p| {
+            let segments = rewire(parse_pattern(p, layout.segments), &wiring);
+            layout.digits.iter().position(|&d| d == segments)
+        })
+        .collect::<Option<_>>()
+        .ok_or(WiringError::NoWiring)?;
+    Ok(Decoded { wiring, output })
+}
+
+fn parse_entry(line: &str) -> (Vec<&str>, Vec<&str>) {
+    let (patterns, output) = line.split_once('|').unwrap();
+    (
+        patterns.split_ascii_whitespace().collect(),
+        output.split_ascii_whitespace().collect(),
+    )
+}
+
 fn part_1(input: aoc::Input) -> impl ToString {
+    let unique_lengths = Layout::seven_segment().unique_lengths();
     let result = input
         .raw()
         .lines()
-        .flat_map(|l| l.split_once('|').unwrap().1.split_ascii_whitespace())
-        .filter(|d| matches!(d.len(), 2 | 3 | 4 | 7))
+        .flat_map(|l| parse_entry(l).1)
+        .filter(|d| unique_lengths.contains(&(d.len() as u32)))
         .count();
 
     result
 }
 
 fn part_2(input: aoc::Input) -> impl ToString {
+    let layout = Layout::seven_segment();
     let result = input
         .raw()
-        .split(|&b| b == b'\n')
+        .lines()
         .map(|line| {
-            let mut part = line.splitn(2, |&b| b == b'|');
-            let mut input = part.next().unwrap().split(|&b| b == b' ');
-            let one = input.clone().find(|d| d.len() == 2).unwrap();
-            let four = input.find(|d| d.len() == 4).unwrap();
-            part.next()
-                .unwrap()
-                .split(|&b| b == b' ')
-                .skip(1)
-                .map(|d| match d.len() {
-                    2 => 1,
-                    3 => 7,
-                    4 => 4,
-                    7 => 8,
-                    len => match (
-                        len,
-                        d.iter().filter(|&b| one.contains(b)).count(),
-                        d.iter().filter(|&b| four.contains(b)).count(),
-                    ) {
-                        (5, 1, 3) => 5,
-                        (5, 2, 3) => 3,
-                        (5, _, 2) => 2,
-                        (6, 1, _) => 6,
-                        (6, _, 3) => 0,
-                        (6, _, 4) => 9,
-                        _ => unreachable!(),
-                    },
-                })
-                .enumerate()
-                .fold(0, |acc, (i, n)| acc + n * 10_u32.pow(3 - i as u32))
+            let (patterns, output) = parse_entry(line);
+            let decoded = decode(&layout, &patterns, &output)
+                .unwrap_or_else(|err| panic!("{err:?} for {line:?}"));
+            decoded.value(layout.digits.len())
         })
-        .sum::<u32>();
+        .sum::<u64>();
 
     result
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_decode() {
+        let line =
+            "acedgfb cdfbe gcdfa fbcad dab cefabd cdfgeb eafb cagedb ab | cdfeb fcadb cdfeb cdbaf";
+        let (patterns, output) = parse_entry(line);
+        let decoded = decode(&Layout::seven_segment(), &patterns, &output).unwrap();
+        // Wire a goes to segment c, b to f, and so on.
+        assert_eq!(decoded.wiring, [2, 5, 6, 0, 1, 3, 4]);
+        assert_eq!(decoded.output, [5, 3, 5, 3]);
+        assert_eq!(decoded.value(10), 5353);
+    }
+
+    #[test]
+    fn test_invalid_patterns() {
+        let layout = Layout::seven_segment();
+        // Two patterns for 1 and none for 7.
+        let patterns = [
+            "acedgfb", "cdfbe", "gcdfa", "fbcad", "bd", "cefabd", "cdfgeb", "eafb", "cagedb", "ab",
+        ];
+        assert_eq!(
+            decode(&layout, &patterns, &["ab"]),
+            Err(WiringError::NoWiring)
+        );
+    }
+
+    #[test]
+    fn test_alternate_layout() {
+        // A three segment display whose digits are indistinguishable under swapping b and c.
+        let symmetric = Layout {
+            segments: 3,
+            digits: vec![0b001, 0b110, 0b111],
+        };
+        assert_eq!(
+            decode(&symmetric, &["a", "bc", "abc"], &["a"]),
+            Err(WiringError::Ambiguous)
+        );
+
+        // Counting in base 4 on a four segment display.
+        let layout = Layout {
+            segments: 4,
+            digits: vec![0b0001, 0b0011, 0b0111, 0b1110],
+        };
+        // Wires a, b, c and d go to segments d, c, b and a.
+        let decoded = decode(&layout, &["d", "cd", "bcd", "abc"], &["abc", "d", "bcd"]).unwrap();
+        assert_eq!(decoded.wiring, [3, 2, 1, 0]);
+        assert_eq!(decoded.value(4), 3 * 16 + 2);
+        assert_eq!(layout.unique_lengths(), [1, 2]);
+    }
+
+    #[test]
+    #[should_panic(expected = "unknown wire 'A'")]
+    fn test_unknown_wire() {
+        parse_pattern("abA", 7);
+    }
+}
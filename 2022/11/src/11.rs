@@ -1,10 +1,159 @@
-use std::collections::VecDeque;
-use std::sync::Arc;
+use std::collections::{HashMap, VecDeque};
+
+/// A monkey's operation: arithmetic over the old worry level and constants.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Expr {
+    Old,
+    Const(u64),
+    Add(Box<Expr>, Box<Expr>),
+    Sub(Box<Expr>, Box<Expr>),
+    Mul(Box<Expr>, Box<Expr>),
+    Div(Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /// Evaluates the expression for `old`, reducing modulo `modulo` after every operation when
+    /// one is given. Panics on overflow, negative results or division by zero.
+    fn eval(&self, old: u64, modulo: Option<u64>) -> u64 {
+        let reduce = |value: u64| modulo.map_or(value, |m| value % m);
+        let binary = |a: &Expr, b: &Expr| (a.eval(old, modulo), b.eval(old, modulo));
+        match self {
+            Expr::Old => reduce(old),
+            Expr::Const(value) => reduce(*value),
+            Expr::Add(a, b) => {
+                let (a, b) = binary(a, b);
+                reduce(a.checked_add(b).expect("worry level overflowed"))
+            }
+            Expr::Sub(a, b) => {
+                let (a, b) = binary(a, b);
+                match modulo {
+                    Some(m) => (a + m - b) % m,
+                    None => a.checked_sub(b).expect("worry level went negative"),
+                }
+            }
+            Expr::Mul(a, b) => {
+                let (a, b) = binary(a, b);
+                let product = a as u128 * b as u128;
+                match modulo {
+                    Some(m) => (product % m as u128) as u64,
+                    None => u64::try_from(product).expect("worry level overflowed"),
+                }
+            }
+            Expr::Div(a, b) => {
+                assert!(
+                    modulo.is_none(),
+                    "division does not commute with the modulo"
+                );
+                let (a, b) = binary(a, b);
+                a.checked_div(b).expect("division by zero")
+            }
+        }
+    }
+
+    fn has_division(&self) -> bool {
+        match self {
+            Expr::Old | Expr::Const(_) => false,
+            Expr::Div(_, _) => true,
+            Expr::Add(a, b) | Expr::Sub(a, b) | Expr::Mul(a, b) => {
+                a.has_division() || b.has_division()
+            }
+        }
+    }
+}
+
+/// Recursive-descent parser for `+ - * /` with the usual precedence and parentheses.
+struct ExprParser<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn tokenize(source: &'a str) -> Vec<&'a str> {
+        let mut tokens = Vec::new();
+        let mut rest = source.trim_start();
+        while let Some(first) = rest.chars().next() {
+            let length = if first.is_ascii_alphanumeric() {
+                rest.find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len())
+            } else {
+                first.len_utf8()
+            };
+            tokens.push(&rest[..length]);
+            rest = rest[length..].trim_start();
+        }
+        tokens
+    }
+
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut expr = self.term()?;
+        while let Some(op @ ("+" | "-")) = self.peek() {
+            self.position += 1;
+            let rhs = Box::new(self.term()?);
+            expr = match op {
+                "+" => Expr::Add(Box::new(expr), rhs),
+                _ => Expr::Sub(Box::new(expr), rhs),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut expr = self.factor()?;
+        while let Some(op @ ("*" | "/")) = self.peek() {
+            self.position += 1;
+            let rhs = Box::new(self.factor()?);
+            expr = match op {
+                "*" => Expr::Mul(Box::new(expr), rhs),
+                _ => Expr::Div(Box::new(expr), rhs),
+            };
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some("old") => Ok(Expr::Old),
+            Some("(") => {
+                let expr = self.expression()?;
+                match self.next() {
+                    Some(")") => Ok(expr),
+                    other => Err(format!("expected ')', found {:?}", other)),
+                }
+            }
+            Some(token) => token
+                .parse()
+                .map(Expr::Const)
+                .map_err(|_| format!("unexpected token {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+}
+
+fn parse_expression(source: &str) -> Result<Expr, String> {
+    let mut parser = ExprParser {
+        tokens: ExprParser::tokenize(source),
+        position: 0,
+    };
+    let expr = parser.expression()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected token {:?}", token)),
+    }
+}
 
-#[derive(Clone)]
 struct Monkey {
     items: VecDeque<u64>,
-    operation: Arc<dyn Fn(u64) -> u64 + Send + Sync>,
+    operation: Expr,
     test_divisor: u64,
     if_true: usize,
     if_false: usize,
@@ -12,21 +161,18 @@ struct Monkey {
 }
 
 impl Monkey {
-    fn inspect_and_throw(&mut self, monkeys: &mut [Monkey], modulo: Option<u64>) {
-        while let Some(item) = self.items.pop_front() {
-            self.inspections += 1;
-            let new_worry = if let Some(m) = modulo {
-                (self.operation)(item) % m
-            } else {
-                (self.operation)(item) / 3
-            };
-            let target = if new_worry % self.test_divisor == 0 {
-                self.if_true
-            } else {
-                self.if_false
-            };
-            monkeys[target].items.push_back(new_worry);
-        }
+    /// Inspects an item, returning the monkey it is thrown to and its new worry level.
+    fn throw(&self, worry: u64, modulo: Option<u64>) -> (usize, u64) {
+        let new_worry = match modulo {
+            Some(m) => self.operation.eval(worry, Some(m)),
+            None => self.operation.eval(worry, None) / 3,
+        };
+        let target = if new_worry % self.test_divisor == 0 {
+            self.if_true
+        } else {
+            self.if_false
+        };
+        (target, new_worry)
     }
 }
 
@@ -42,20 +188,9 @@ fn parse_input(input: &str) -> Vec<Monkey> {
             .map(|x| x.parse::<u64>().unwrap())
             .collect();
 
-        let operation_str = lines[2].strip_prefix("  Operation: new = old ").unwrap();
-        let operation: Arc<dyn Fn(u64) -> u64 + Send + Sync> = if operation_str.starts_with('*') {
-            if operation_str.trim() == "* old" {
-                Arc::new(move |old| old * old)
-            } else {
-                let factor = operation_str[2..].parse::<u64>().unwrap();
-                Arc::new(move |old| old * factor)
-            }
-        } else if operation_str.starts_with('+') {
-            let addend = operation_str[2..].parse::<u64>().unwrap();
-            Arc::new(move |old| old + addend)
-        } else {
-            panic!("Unexpected operation: {}", operation_str);
-        };
+        let operation_str = lines[2].strip_prefix("  Operation: new = ").unwrap();
+        let operation = parse_expression(operation_str)
+            .unwrap_or_else(|error| panic!("Unexpected operation {}: {}", operation_str, error));
 
         let test_divisor = lines[3]
             .strip_prefix("  Test: divisible by ")
@@ -88,40 +223,256 @@ fn parse_input(input: &str) -> Vec<Monkey> {
     monkeys
 }
 
+fn gcd(a: u64, b: u64) -> u64 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Least common multiple of the test divisors: reducing worry levels modulo it keeps every
+/// divisibility test unchanged.
+fn divisor_lcm(monkeys: &[Monkey]) -> u64 {
+    monkeys
+        .iter()
+        .map(|m| m.test_divisor)
+        .fold(1, |lcm, divisor| lcm / gcd(lcm, divisor) * divisor)
+}
+
+/// Plays `rounds` rounds. Without a modulo worry levels are divided by 3 after each inspection.
+///
+/// Each monkey's queue is taken out while it throws, so items move between queues without
+/// cloning any monkey.
 fn simulate(monkeys: &mut [Monkey], rounds: usize, modulo: Option<u64>) {
+    if modulo.is_some() {
+        assert!(
+            monkeys.iter().all(|m| !m.operation.has_division()),
+            "division does not commute with the modulo"
+        );
+    }
+
     for _ in 0..rounds {
         for i in 0..monkeys.len() {
-            let mut monkey = monkeys[i].clone();
-            monkey.inspect_and_throw(monkeys, modulo);
-            monkeys[i] = monkey;
+            let items = std::mem::take(&mut monkeys[i].items);
+            monkeys[i].inspections += items.len() as u64;
+            for item in items {
+                let (target, new_worry) = monkeys[i].throw(item, modulo);
+                monkeys[target].items.push_back(new_worry);
+            }
+        }
+    }
+}
+
+/// Follows one item through a single round starting at `monkey`, counting its inspections.
+/// Returns the monkey holding it and its worry level at the start of the next round.
+fn item_round(
+    monkeys: &[Monkey],
+    mut monkey: usize,
+    mut worry: u64,
+    modulo: u64,
+    inspections: &mut [u64],
+) -> (usize, u64) {
+    loop {
+        inspections[monkey] += 1;
+        let (target, new_worry) = monkeys[monkey].throw(worry, Some(modulo));
+        worry = new_worry;
+        // Monkeys take turns in order, so an item thrown forward is inspected again this round.
+        // One thrown back, or to the monkey itself, waits for the next round.
+        if target <= monkey {
+            return (target, worry);
         }
+        monkey = target;
     }
 }
 
+/// Inspections per monkey after `rounds` rounds in the modulo mode, without playing every round.
+///
+/// Items never affect each other, and an item's whole future depends only on which monkey holds
+/// it and its worry level modulo `modulo` at the start of a round. There are finitely many such
+/// states, so each item's trajectory runs into a cycle, and the inspections of all the remaining
+/// whole cycles can be added at once.
+fn inspections_by_item_cycles(monkeys: &[Monkey], rounds: u64, modulo: u64) -> Vec<u64> {
+    let mut total = vec![0; monkeys.len()];
+
+    for (start_monkey, monkey) in monkeys.iter().enumerate() {
+        for &item in &monkey.items {
+            let mut state = (start_monkey, item % modulo);
+            let mut first_seen: HashMap<(usize, u64), usize> = HashMap::new();
+            // Inspections of this item after each number of rounds played so far.
+            let mut history = vec![vec![0; monkeys.len()]];
+
+            let counts = loop {
+                let played = history.len() - 1;
+                if played as u64 == rounds {
+                    break history[played].clone();
+                }
+                if let Some(&cycle_start) = first_seen.get(&state) {
+                    let cycle_length = (played - cycle_start) as u64;
+                    let remaining = rounds - played as u64;
+                    let partial = cycle_start + (remaining % cycle_length) as usize;
+                    break (0..monkeys.len())
+                        .map(|i| {
+                            let per_cycle = history[played][i] - history[cycle_start][i];
+                            history[played][i]
+                                + remaining / cycle_length * per_cycle
+                                + (history[partial][i] - history[cycle_start][i])
+                        })
+                        .collect();
+                }
+                first_seen.insert(state, played);
+
+                let mut counts = history[played].clone();
+                state = item_round(monkeys, state.0, state.1, modulo, &mut counts);
+                history.push(counts);
+            };
+
+            for (total, count) in total.iter_mut().zip(counts) {
+                *total += count;
+            }
+        }
+    }
+
+    total
+}
+
+fn monkey_business(monkeys: &[Monkey]) -> u64 {
+    let inspections: Vec<_> = monkeys.iter().map(|m| m.inspections).collect();
+    top_two_product(inspections)
+}
+
+fn top_two_product(mut inspections: Vec<u64>) -> u64 {
+    inspections.sort_unstable_by(|a, b| b.cmp(a));
+
+    inspections[0] * inspections[1]
+}
+
 aoc::parts!(1, 2);
 
 fn part_1(input: aoc::Input) -> impl ToString {
     let raw_input = input.raw();
-    let mut monkeys = parse_input(&raw_input);
+    let mut monkeys = parse_input(raw_input);
     simulate(&mut monkeys, 20, None); // No modulo for part 1
 
-    let mut inspections: Vec<_> = monkeys.iter().map(|m| m.inspections).collect();
-    inspections.sort_unstable_by(|a, b| b.cmp(a));
-
-    inspections[0] * inspections[1]
+    monkey_business(&monkeys)
 }
 
 fn part_2(input: aoc::Input) -> impl ToString {
     let raw_input = input.raw();
-    let mut monkeys = parse_input(&raw_input);
+    let monkeys = parse_input(raw_input);
 
-    // Calculate the least common multiple of all test divisors
-    let modulo: u64 = monkeys.iter().map(|m| m.test_divisor).product();
+    let modulo = divisor_lcm(&monkeys);
+    top_two_product(inspections_by_item_cycles(&monkeys, 10_000, modulo))
+}
 
-    simulate(&mut monkeys, 10_000, Some(modulo)); // Use modulo for part 2
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut inspections: Vec<_> = monkeys.iter().map(|m| m.inspections).collect();
-    inspections.sort_unstable_by(|a, b| b.cmp(a));
+    const EXAMPLE: &str = "Monkey 0:
+  Starting items: 79, 98
+  Operation: new = old * 19
+  Test: divisible by 23
+    If true: throw to monkey 2
+    If false: throw to monkey 3
 
-    inspections[0] * inspections[1]
+Monkey 1:
+  Starting items: 54, 65, 75, 74
+  Operation: new = old + 6
+  Test: divisible by 19
+    If true: throw to monkey 2
+    If false: throw to monkey 0
+
+Monkey 2:
+  Starting items: 79, 60, 97
+  Operation: new = old * old
+  Test: divisible by 13
+    If true: throw to monkey 1
+    If false: throw to monkey 3
+
+Monkey 3:
+  Starting items: 74
+  Operation: new = old + 3
+  Test: divisible by 17
+    If true: throw to monkey 0
+    If false: throw to monkey 1";
+
+    #[test]
+    fn test_example() {
+        let mut monkeys = parse_input(EXAMPLE);
+        simulate(&mut monkeys, 20, None);
+        assert_eq!(monkey_business(&monkeys), 10605);
+
+        let mut monkeys = parse_input(EXAMPLE);
+        let modulo = divisor_lcm(&monkeys);
+        assert_eq!(modulo, 96577);
+        simulate(&mut monkeys, 10_000, Some(modulo));
+        assert_eq!(monkey_business(&monkeys), 2713310158);
+    }
+
+    #[test]
+    fn test_item_cycles() {
+        let monkeys = parse_input(EXAMPLE);
+        let modulo = divisor_lcm(&monkeys);
+        for rounds in [0, 1, 20, 1000, 10_000] {
+            let mut simulated = parse_input(EXAMPLE);
+            simulate(&mut simulated, rounds, Some(modulo));
+            let expected: Vec<_> = simulated.iter().map(|m| m.inspections).collect();
+            assert_eq!(
+                inspections_by_item_cycles(&monkeys, rounds as u64, modulo),
+                expected
+            );
+        }
+        assert_eq!(
+            top_two_product(inspections_by_item_cycles(&monkeys, 10_000, modulo)),
+            2713310158
+        );
+
+        // Monkey 0 sometimes throws to itself; the item waits for the next round.
+        let input = "Monkey 0:
+  Starting items: 4
+  Operation: new = old + 1
+  Test: divisible by 2
+    If true: throw to monkey 1
+    If false: throw to monkey 0
+
+Monkey 1:
+  Starting items: 7
+  Operation: new = old * 3
+  Test: divisible by 5
+    If true: throw to monkey 1
+    If false: throw to monkey 0";
+        let self_throwing = parse_input(input);
+        let modulo = divisor_lcm(&self_throwing);
+        for rounds in [1, 2, 3, 50, 777] {
+            let mut simulated = parse_input(input);
+            simulate(&mut simulated, rounds, Some(modulo));
+            let expected: Vec<_> = simulated.iter().map(|m| m.inspections).collect();
+            assert_eq!(
+                inspections_by_item_cycles(&self_throwing, rounds as u64, modulo),
+                expected
+            );
+        }
+
+        // A billion rounds only take as long as finding each item's cycle.
+        let few = inspections_by_item_cycles(&monkeys, 10_000, modulo);
+        let many = inspections_by_item_cycles(&monkeys, 1_000_000_000, modulo);
+        assert!(few.iter().zip(&many).all(|(few, many)| many > few));
+    }
+
+    #[test]
+    fn test_parse_expression() {
+        let expr = parse_expression("(old + 2) * old - 3 * (4 - 1)").unwrap();
+        assert_eq!(expr.eval(5, None), 26);
+        assert_eq!(expr.eval(5, Some(7)), 5);
+        assert_eq!(parse_expression("old / 2 + 1").unwrap().eval(9, None), 5);
+        assert!(
+            parse_expression("old * old").unwrap()
+                == Expr::Mul(Box::new(Expr::Old), Box::new(Expr::Old))
+        );
+
+        assert!(parse_expression("old *").is_err());
+        assert!(parse_expression("(old + 1").is_err());
+        assert!(parse_expression("old ^ 2").is_err());
+    }
 }
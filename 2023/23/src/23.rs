@@ -1,8 +1,9 @@
 use hashbrown::HashMap;
 
-use std::collections::VecDeque;
+use std::thread;
 
-type Graph = HashMap<(i32, i32), Vec<((i32, i32), u32)>>;
+/// Number of DFS levels expanded before the remaining searches are spread across threads.
+const PARALLEL_DEPTH: usize = 3;
 
 fn successors(pos: (i32, i32), grid: &[Vec<u8>], is_slippery: bool) -> Vec<((i32, i32), u32)> {
     let width = grid[0].len() as i32;
@@ -46,121 +47,156 @@ fn successors(pos: (i32, i32), grid: &[Vec<u8>], is_slippery: bool) -> Vec<((i32
         .collect()
 }
 
-fn grid_to_graph(grid: &[Vec<u8>], start: (i32, i32), end: (i32, i32), is_slippery: bool) -> Graph {
-    let mut graph = HashMap::new();
-    let mut to_visit = VecDeque::from([start]);
-    while let Some(pos) = to_visit.pop_front() {
-        let next = if pos == end {
-            vec![]
-        } else {
-            successors(pos, grid, is_slippery)
-        };
-        for &(pos, _) in &next {
-            if !graph.contains_key(&pos) && pos != end {
-                to_visit.push_back(pos);
+/// The trail network reduced to its junctions (cells where paths fork, plus the start and end),
+/// numbered `0..edges.len()`, with the longest corridor between each pair of them.
+struct JunctionGraph {
+    edges: Vec<Vec<(usize, u32)>>,
+    start: usize,
+    end: usize,
+}
+
+fn build_junction_graph(
+    grid: &[Vec<u8>],
+    start: (i32, i32),
+    end: (i32, i32),
+    is_slippery: bool,
+) -> JunctionGraph {
+    let mut ids: HashMap<(i32, i32), usize> = HashMap::from([(start, 0), (end, 1)]);
+    for (y, row) in grid.iter().enumerate() {
+        for (x, &cell) in row.iter().enumerate() {
+            let pos = (x as i32, y as i32);
+            if cell != b'#' && successors(pos, grid, false).len() > 2 {
+                let id = ids.len();
+                ids.entry(pos).or_insert(id);
             }
         }
-        graph.insert(pos, next);
     }
-    graph
-}
-
-fn keep_only_longest_to_same_destination(next: &[((i32, i32), u32)]) -> Vec<((i32, i32), u32)> {
-    let mut longest: HashMap<(i32, i32), u32> = HashMap::new();
-    next.iter().for_each(|&(pos, distance)| {
-        longest
-            .entry(pos)
-            .and_modify(|d| {
-                if *d < distance {
-                    *d = distance;
-                }
-            })
-            .or_insert(distance);
-    });
-    longest.into_iter().collect()
-}
+    assert!(ids.len() <= 64, "too many junctions for a u64 visited set");
 
-fn graph_simplification_step(graph: &Graph, start: (i32, i32), end: (i32, i32)) -> Graph {
-    let mut simpler_graph = HashMap::new();
-    let mut to_visit = VecDeque::from([start]);
-    while let Some(crossroad_pos) = to_visit.pop_front() {
-        if simpler_graph.contains_key(&crossroad_pos) {
+    let mut edges = vec![Vec::new(); ids.len()];
+    for (&junction, &id) in &ids {
+        if junction == end {
             continue;
         }
-        let mut next_crossroads = vec![];
-        'branches: for &(branch_start, distance) in graph.get(&crossroad_pos).unwrap_or(&vec![]) {
+        'branches: for (branch_start, distance) in successors(junction, grid, is_slippery) {
             let mut steps = distance;
-            let mut prev_pos = crossroad_pos;
+            let mut prev_pos = junction;
             let mut pos = branch_start;
-            loop {
-                if pos == crossroad_pos || pos == start {
-                    continue 'branches;
-                }
-                if pos == end {
-                    break;
-                }
-                let mut next = graph.get(&pos).unwrap_or(&vec![]).clone();
-                next.retain(|(next_pos, _)| *next_pos != prev_pos);
-                match next.len() {
-                    0 => {
-                        continue 'branches;
-                    }
-                    1 => {
+            while !ids.contains_key(&pos) {
+                let next: Vec<_> = successors(pos, grid, is_slippery)
+                    .into_iter()
+                    .filter(|&(next_pos, _)| next_pos != prev_pos)
+                    .collect();
+                match next[..] {
+                    [(next_pos, distance)] => {
                         prev_pos = pos;
-                        pos = next[0].0;
-                        steps += next[0].1;
-                    }
-                    _ => {
-                        break;
+                        pos = next_pos;
+                        steps += distance;
                     }
-                };
+                    // A dead end, or a slope we cannot climb.
+                    _ => continue 'branches,
+                }
+            }
+
+            let target = ids[&pos];
+            match edges[id].iter_mut().find(|(next, _)| *next == target) {
+                Some((_, longest)) => *longest = steps.max(*longest),
+                None => edges[id].push((target, steps)),
             }
-            next_crossroads.push((pos, steps));
         }
-        simpler_graph.insert(
-            crossroad_pos,
-            keep_only_longest_to_same_destination(&next_crossroads),
-        );
-        next_crossroads
-            .into_iter()
-            .for_each(|(pos, _)| to_visit.push_back(pos))
     }
 
-    simpler_graph
+    JunctionGraph {
+        edges,
+        start: ids[&start],
+        end: ids[&end],
+    }
 }
 
-fn simplify_graph(graph: &Graph, start: (i32, i32), end: (i32, i32)) -> Graph {
-    let mut graph_size = graph.len();
-    let mut simpler_graph = graph_simplification_step(graph, start, end);
-    while simpler_graph.len() < graph_size {
-        graph_size = simpler_graph.len();
-        simpler_graph = graph_simplification_step(graph, start, end);
+impl JunctionGraph {
+    /// The only junction leading to the end, if there is just one.
+    ///
+    /// Once a path reaches it, it has to head straight for the end: anywhere else and the end
+    /// can never be reached.
+    fn last_junction(&self) -> Option<usize> {
+        let mut leading_to_end = (0..self.edges.len())
+            .filter(|&node| self.edges[node].iter().any(|&(next, _)| next == self.end));
+        match (leading_to_end.next(), leading_to_end.next()) {
+            (Some(node), None) => Some(node),
+            _ => None,
+        }
     }
-    simpler_graph
-}
 
-fn find_longest_path(graph: &Graph, start: (i32, i32), end: (i32, i32)) -> Option<u32> {
-    if start == end {
-        return Some(0);
+    /// Edges from `node` to junctions outside the `visited` bitmask that can still lead to the end.
+    fn moves(
+        &self,
+        node: usize,
+        visited: u64,
+        last: Option<usize>,
+    ) -> impl Iterator<Item = &(usize, u32)> {
+        self.edges[node]
+            .iter()
+            .filter(move |&&(next, _)| visited & 1 << next == 0)
+            .filter(move |&&(next, _)| Some(node) != last || next == self.end)
     }
-    let mut graph = simplify_graph(graph, start, end);
-
-    let next = graph.remove(&start);
-    if let Some(next) = next {
-        next.iter()
-            .filter_map(|&(next_pos, distance)| {
-                find_longest_path(&graph, next_pos, end)
-                    .map(|longest_remaining| longest_remaining + distance)
+
+    /// Longest path from `node` to the end that avoids every junction in the `visited` bitmask.
+    fn longest_from(&self, node: usize, visited: u64, last: Option<usize>) -> Option<u32> {
+        if node == self.end {
+            return Some(0);
+        }
+        let visited = visited | 1 << node;
+        self.moves(node, visited, last)
+            .filter_map(|&(next, distance)| {
+                self.longest_from(next, visited, last)
+                    .map(|remaining| remaining + distance)
             })
             .max()
-    } else {
-        None
+    }
+
+    /// Longest path from start to end without revisiting a junction.
+    ///
+    /// The first `PARALLEL_DEPTH` levels of the search are expanded here, and the searches from
+    /// each of those partial paths run on their own threads.
+    fn longest_path(&self) -> Option<u32> {
+        let last = self.last_junction();
+        let mut frontier = vec![(self.start, 0u64, 0u32)];
+        for _ in 0..PARALLEL_DEPTH {
+            frontier = frontier
+                .into_iter()
+                .flat_map(|(node, visited, distance)| {
+                    let visited = visited | 1 << node;
+                    let branches: Vec<_> = if node == self.end {
+                        vec![(node, visited, distance)]
+                    } else {
+                        self.moves(node, visited, last)
+                            .map(|&(next, step)| (next, visited, distance + step))
+                            .collect()
+                    };
+                    branches
+                })
+                .collect();
+        }
+
+        thread::scope(|scope| {
+            let searches: Vec<_> = frontier
+                .iter()
+                .map(|&(node, visited, distance)| {
+                    scope.spawn(move || {
+                        self.longest_from(node, visited, last)
+                            .map(|remaining| remaining + distance)
+                    })
+                })
+                .collect();
+            searches
+                .into_iter()
+                .filter_map(|search| search.join().unwrap())
+                .max()
+        })
     }
 }
-aoc::parts!(1, 2);
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let grid: Vec<_> = input.lines().map(|line| line.as_bytes().to_vec()).collect();
+fn find_longest_path(grid: &[Vec<u8>], is_slippery: bool) -> Option<u32> {
     let start = (grid[0].iter().position(|&c| c == b'.').unwrap() as i32, 0);
     let end = (
         grid.last()
@@ -170,23 +206,57 @@ fn part_1(input: aoc::Input) -> impl ToString {
             .unwrap() as i32,
         (grid.len() - 1) as i32,
     );
-    let graph = grid_to_graph(&grid, start, end, true);
-    let max_steps = find_longest_path(&graph, start, end).unwrap();
-    max_steps
+    build_junction_graph(grid, start, end, is_slippery).longest_path()
+}
+
+aoc::parts!(1, 2);
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    let grid: Vec<_> = input.lines().map(|line| line.as_bytes().to_vec()).collect();
+    find_longest_path(&grid, true).unwrap()
 }
 
 fn part_2(input: aoc::Input) -> impl ToString {
     let grid: Vec<_> = input.lines().map(|line| line.as_bytes().to_vec()).collect();
-    let start = (grid[0].iter().position(|&c| c == b'.').unwrap() as i32, 0);
-    let end = (
-        grid.last()
-            .unwrap()
-            .iter()
-            .position(|&c| c == b'.')
-            .unwrap() as i32,
-        (grid.len() - 1) as i32,
-    );
-    let graph = grid_to_graph(&grid, start, end, false);
-    let max_steps = find_longest_path(&graph, start, end).unwrap();
-    max_steps
+    find_longest_path(&grid, false).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+#.#####################
+#.......#########...###
+#######.#########.#.###
+###.....#.>.>.###.#.###
+###v#####.#v#.###.#.###
+###.>...#.#.#.....#...#
+###v###.#.#.#########.#
+###...#.#.#.......#...#
+#####.#.#.#######.#.###
+#.....#.#.#.......#...#
+#.#####.#.#.#########v#
+#.#...#...#...###...>.#
+#.#.#v#######v###.###v#
+#...#.>.#...>.>.#.###.#
+#####v#.#.###v#.#.###.#
+#.....#...#...#.#.#...#
+#.#########.###.#.#.###
+#...###...#...#...#.###
+###.###.#.###v#####v###
+#...#...#.#.>.>.#.>.###
+#.###.###.#.###.#.#v###
+#.....###...###...#...#
+#####################.#";
+
+    #[test]
+    fn test_longest_path() {
+        let grid: Vec<_> = EXAMPLE
+            .lines()
+            .map(|line| line.as_bytes().to_vec())
+            .collect();
+        assert_eq!(find_longest_path(&grid, true), Some(94));
+        assert_eq!(find_longest_path(&grid, false), Some(154));
+    }
 }
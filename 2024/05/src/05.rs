@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 aoc::parts!(1, 2);
 fn parse_input(input: &str) -> (Vec<(u32, u32)>, Vec<Vec<u32>>) {
@@ -20,31 +20,119 @@ fn parse_input(input: &str) -> (Vec<(u32, u32)>, Vec<Vec<u32>>) {
     (ordering_rules, updates)
 }
 
-fn is_update_valid(ordering_rules: &[(u32, u32)], update: &[u32]) -> bool {
-    let position: HashMap<u32, usize> = update.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+/// Ordering rules compiled into an adjacency map from each page to the pages that must follow it.
+struct OrderingRules {
+    successors: HashMap<u32, HashSet<u32>>,
+}
 
-    for &(x, y) in ordering_rules {
-        if let (Some(&pos_x), Some(&pos_y)) = (position.get(&x), position.get(&y)) {
-            if pos_x >= pos_y {
-                return false;
-            }
+impl OrderingRules {
+    fn new(ordering_rules: &[(u32, u32)]) -> Self {
+        let mut successors: HashMap<u32, HashSet<u32>> = HashMap::new();
+        for &(x, y) in ordering_rules {
+            successors.entry(x).or_default().insert(y);
         }
+        OrderingRules { successors }
     }
 
-    true
+    fn must_precede(&self, x: u32, y: u32) -> bool {
+        self.successors
+            .get(&x)
+            .is_some_and(|successors| successors.contains(&y))
+    }
 }
 
-fn reorder_update(ordering_rules: &[(u32, u32)], update: &mut Vec<u32>) {
-    update.sort_by(|&a, &b| {
-        for &(x, y) in ordering_rules {
-            if x == a && y == b {
-                return std::cmp::Ordering::Less;
-            } else if x == b && y == a {
-                return std::cmp::Ordering::Greater;
+#[derive(Debug, PartialEq, Eq)]
+enum OrderError {
+    /// The rules between the update's pages form a cycle through these pages.
+    Cycle(Vec<u32>),
+    /// No rule, direct or implied, decides which of these two pages comes first.
+    Ambiguous(u32, u32),
+    /// The page appears more than once, so it has no single place in the order.
+    Duplicate(u32),
+}
+
+fn is_update_valid(rules: &OrderingRules, update: &[u32]) -> bool {
+    update.iter().enumerate().all(|(i, &later)| {
+        update[..i]
+            .iter()
+            .all(|&earlier| !rules.must_precede(later, earlier))
+    })
+}
+
+/// Sorts the update topologically using only the rules between its own pages.
+///
+/// The order has to be unique, so there must be exactly one page with nothing left before it at
+/// every step; otherwise the update is ambiguous, or, if there is none, the rules are cyclic.
+fn reorder_update(rules: &OrderingRules, update: &mut Vec<u32>) -> Result<(), OrderError> {
+    let mut pages = HashSet::new();
+    if let Some(&page) = update.iter().find(|&&page| !pages.insert(page)) {
+        return Err(OrderError::Duplicate(page));
+    }
+    let mut in_degree: HashMap<u32, usize> = pages.iter().map(|&page| (page, 0)).collect();
+    for &page in &pages {
+        for &next in rules.successors.get(&page).into_iter().flatten() {
+            if let Some(degree) = in_degree.get_mut(&next) {
+                *degree += 1;
+            }
+        }
+    }
+
+    let mut ordered = Vec::with_capacity(update.len());
+    while !in_degree.is_empty() {
+        let mut ready: Vec<u32> = in_degree
+            .iter()
+            .filter(|&(_, &degree)| degree == 0)
+            .map(|(&page, _)| page)
+            .collect();
+        // Sorted so that the same update always reports the same pair.
+        ready.sort_unstable();
+        let page = match ready[..] {
+            [page] => page,
+            [a, b, ..] => return Err(OrderError::Ambiguous(a, b)),
+            [] => {
+                let remaining: HashSet<u32> = in_degree.keys().copied().collect();
+                return Err(OrderError::Cycle(find_cycle(rules, &remaining)));
+            }
+        };
+
+        in_degree.remove(&page);
+        ordered.push(page);
+        for next in rules.successors.get(&page).into_iter().flatten() {
+            if let Some(degree) = in_degree.get_mut(next) {
+                *degree -= 1;
             }
         }
-        std::cmp::Ordering::Equal
-    });
+    }
+
+    *update = ordered;
+    Ok(())
+}
+
+/// A cycle among `pages`, each of which has a predecessor in `pages`.
+fn find_cycle(rules: &OrderingRules, pages: &HashSet<u32>) -> Vec<u32> {
+    let predecessor = |page: u32| {
+        pages
+            .iter()
+            .copied()
+            .filter(|&candidate| rules.must_precede(candidate, page))
+            .min()
+            .unwrap()
+    };
+
+    // Walking backwards must revisit a page, and everything from there on is the cycle.
+    let mut path = vec![*pages.iter().min().unwrap()];
+    loop {
+        let previous = predecessor(*path.last().unwrap());
+        if let Some(start) = path.iter().position(|&page| page == previous) {
+            let mut cycle = path.split_off(start);
+            cycle.reverse();
+            // Start from the smallest page so the same cycle is always reported the same way.
+            let smallest = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap();
+            cycle.rotate_left(smallest);
+            return cycle;
+        }
+        path.push(previous);
+    }
 }
 
 fn find_middle_number(update: &[u32]) -> u32 {
@@ -53,12 +141,13 @@ fn find_middle_number(update: &[u32]) -> u32 {
 
 fn part_1(input: aoc::Input) -> impl ToString {
     let raw_input = input.raw();
-    let (ordering_rules, updates) = parse_input(&raw_input);
+    let (ordering_rules, updates) = parse_input(raw_input);
+    let rules = OrderingRules::new(&ordering_rules);
 
     let mut middle_sum = 0;
 
     for update in updates {
-        if is_update_valid(&ordering_rules, &update) {
+        if is_update_valid(&rules, &update) {
             middle_sum += find_middle_number(&update);
         }
     }
@@ -68,13 +157,14 @@ fn part_1(input: aoc::Input) -> impl ToString {
 
 fn part_2(input: aoc::Input) -> impl ToString {
     let raw_input = input.raw();
-    let (ordering_rules, mut updates) = parse_input(&raw_input);
+    let (ordering_rules, mut updates) = parse_input(raw_input);
+    let rules = OrderingRules::new(&ordering_rules);
 
     let mut middle_sum = 0;
 
     for update in updates.iter_mut() {
-        if !is_update_valid(&ordering_rules, update) {
-            reorder_update(&ordering_rules, update);
+        if !is_update_valid(&rules, update) {
+            reorder_update(&rules, update).expect("Update cannot be ordered");
             middle_sum += find_middle_number(update);
         }
     }
@@ -90,10 +180,11 @@ mod tests {
     fn test_example_case() {
         let input = "47|53\n97|13\n97|61\n97|47\n75|29\n61|13\n75|53\n29|13\n97|29\n53|29\n61|53\n97|53\n61|29\n47|13\n75|47\n97|75\n47|61\n75|61\n47|29\n75|13\n53|13\n\n75,47,61,53,29\n97,61,53,29,13\n75,29,13\n75,97,47,61,53\n61,13,29\n97,13,75,29,47";
         let (ordering_rules, updates) = parse_input(input);
+        let rules = OrderingRules::new(&ordering_rules);
 
         let valid_updates: Vec<_> = updates
             .iter()
-            .filter(|update| is_update_valid(&ordering_rules, update))
+            .filter(|update| is_update_valid(&rules, update))
             .cloned()
             .collect();
 
@@ -109,24 +200,62 @@ mod tests {
     #[test]
     fn test_reorder_case() {
         let input = "47|53\n97|13\n97|61\n97|47\n75|29\n61|13\n75|53\n29|13\n97|29\n53|29\n61|53\n97|53\n61|29\n47|13\n75|47\n97|75\n47|61\n75|61\n47|29\n75|13\n53|13\n\n75,47,61,53,29\n97,61,53,29,13\n75,29,13\n75,97,47,61,53\n61,13,29\n97,13,75,29,47";
-        let (ordering_rules, mut updates) = parse_input(input);
+        let (ordering_rules, updates) = parse_input(input);
+        let rules = OrderingRules::new(&ordering_rules);
 
-        let invalid_updates: Vec<_> = updates
-            .iter_mut()
-            .filter(|update| !is_update_valid(&ordering_rules, update))
+        let mut invalid_updates: Vec<_> = updates
+            .into_iter()
+            .filter(|update| !is_update_valid(&rules, update))
             .collect::<Vec<_>>();
 
-        for update in invalid_updates {
-            reorder_update(&ordering_rules, update);
+        for update in invalid_updates.iter_mut() {
+            reorder_update(&rules, update).unwrap();
+            assert!(is_update_valid(&rules, update));
         }
 
-        let reordered_middle_numbers: Vec<u32> = updates
+        let reordered_middle_numbers: Vec<u32> = invalid_updates
             .iter()
-            .filter(|update| !is_update_valid(&ordering_rules, update))
             .map(|update| find_middle_number(update))
             .collect();
 
         assert_eq!(reordered_middle_numbers, vec![47, 29, 47]);
         assert_eq!(reordered_middle_numbers.iter().sum::<u32>(), 123);
     }
+
+    #[test]
+    fn test_reorder_errors() {
+        let rules = OrderingRules::new(&[(1, 2), (2, 3), (3, 1), (4, 5)]);
+
+        let mut update = vec![3, 2, 1];
+        assert_eq!(
+            reorder_update(&rules, &mut update),
+            Err(OrderError::Cycle(vec![1, 2, 3]))
+        );
+        assert_eq!(update, vec![3, 2, 1]);
+
+        let mut update = vec![5, 4, 6];
+        assert_eq!(
+            reorder_update(&rules, &mut update),
+            Err(OrderError::Ambiguous(4, 6))
+        );
+
+        // With several unordered pages, the two smallest are reported.
+        let mut update = vec![9, 7, 8];
+        assert_eq!(
+            reorder_update(&rules, &mut update),
+            Err(OrderError::Ambiguous(7, 8))
+        );
+
+        // Only the rules between the update's own pages apply, so the cycle does not matter here.
+        let mut update = vec![3, 2];
+        assert_eq!(reorder_update(&rules, &mut update), Ok(()));
+        assert_eq!(update, vec![2, 3]);
+
+        let mut update = vec![3, 2, 3];
+        assert_eq!(
+            reorder_update(&rules, &mut update),
+            Err(OrderError::Duplicate(3))
+        );
+        assert_eq!(update, vec![3, 2, 3]);
+    }
 }
@@ -1,74 +1,78 @@
-use num_bigint::BigUint;
-use num_traits::FromPrimitive;
-use rayon::prelude::*;
-use std::str::FromStr;
+use std::collections::HashMap;
 
 aoc::parts!(1, 2);
 
-fn remove_leading_zeros(s: &str) -> String {
-    let trimmed = s.trim_start_matches('0');
-    if trimmed.is_empty() {
-        "0".to_string()
-    } else {
-        trimmed.to_string()
+/// Stone value to the number of stones engraved with it. Stones never interact, so their order
+/// does not matter.
+type Stones = HashMap<u64, u64>;
+
+fn parse_stones(input: &str) -> Stones {
+    let mut stones = Stones::new();
+    for stone in input.split_whitespace() {
+        *stones.entry(stone.parse().unwrap()).or_insert(0) += 1;
     }
+    stones
 }
 
-fn blink(stones: Vec<String>) -> Vec<String> {
-    let two_thousand_twenty_four = BigUint::from_u32(2024).unwrap();
+/// The stones a single stone turns into, or `None` if its new value does not fit in a `u64`.
+fn blink_stone(stone: u64) -> Option<(u64, Option<u64>)> {
+    if stone == 0 {
+        // Rule 1: 0 -> 1
+        return Some((1, None));
+    }
+    let digits = stone.ilog10() + 1;
+    if digits.is_multiple_of(2) {
+        // Rule 2: even number of digits -> split into two stones
+        let half = 10u64.pow(digits / 2);
+        Some((stone / half, Some(stone % half)))
+    } else {
+        // Rule 3: multiply by 2024
+        Some((stone.checked_mul(2024)?, None))
+    }
+}
 
-    // Process each stone in parallel:
-    stones
-        .into_par_iter()
-        .flat_map(|stone| {
-            if stone == "0" {
-                // Rule 1: 0 -> 1
-                vec!["1".to_string()]
-            } else if stone.len() % 2 == 0 {
-                // Rule 2: even length -> split into two stones
-                let mid = stone.len() / 2;
-                let left_part = &stone[..mid];
-                let right_part = &stone[mid..];
-                vec![
-                    remove_leading_zeros(left_part),
-                    remove_leading_zeros(right_part),
-                ]
-            } else {
-                // Rule 3: multiply by 2024
-                let num = BigUint::from_str(&stone).expect("valid number");
-                let multiplied = &num * &two_thousand_twenty_four;
-                vec![multiplied.to_string()]
-            }
-        })
-        .collect()
+/// One blink over every stone, or `None` if a stone value or count overflows.
+fn blink(stones: &Stones) -> Option<Stones> {
+    let mut next = Stones::with_capacity(stones.len());
+    for (&stone, &count) in stones {
+        let (left, right) = blink_stone(stone)?;
+        for value in [Some(left), right].into_iter().flatten() {
+            let entry = next.entry(value).or_insert(0);
+            *entry = entry.checked_add(count)?;
+        }
+    }
+    Some(next)
 }
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let mut stones: Vec<String> = input
-        .raw()
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect();
+/// Total number of stones and number of distinct stone values after each of the `blinks`
+/// blinks, or `None` if a value or count overflows.
+fn blink_stats(stones: &Stones, blinks: usize) -> Option<Vec<(u64, usize)>> {
+    let mut stones = stones.clone();
+    let mut stats = Vec::with_capacity(blinks);
+    for _ in 0..blinks {
+        stones = blink(&stones)?;
+        let total = stones
+            .values()
+            .try_fold(0u64, |total, &count| total.checked_add(count))?;
+        stats.push((total, stones.len()));
+    }
+    Some(stats)
+}
 
-    for _ in 0..25 {
-        stones = blink(stones);
+/// Number of stones after `blinks` blinks, or `None` on overflow.
+fn count_stones(stones: &Stones, blinks: usize) -> Option<u64> {
+    match blink_stats(stones, blinks)?.last() {
+        Some(&(total, _)) => Some(total),
+        None => Some(stones.values().sum()),
     }
+}
 
-    stones.len()
+fn part_1(input: aoc::Input) -> impl ToString {
+    count_stones(&parse_stones(input.raw()), 25).expect("stone count overflowed")
 }
 
 fn part_2(input: aoc::Input) -> impl ToString {
-    let mut stones: Vec<String> = input
-        .raw()
-        .split_whitespace()
-        .map(|s| s.to_string())
-        .collect();
-
-    for _ in 0..75 {
-        stones = blink(stones);
-    }
-
-    stones.len()
+    count_stones(&parse_stones(input.raw()), 75).expect("stone count overflowed")
 }
 
 #[cfg(test)]
@@ -77,15 +81,43 @@ mod tests {
 
     #[test]
     fn test_part_1_example() {
-        let input = application::Input::new("125 17");
-        let result = part_1(input).to_string();
-        assert_eq!(result, "55312");
+        let stones = parse_stones("125 17");
+        assert_eq!(count_stones(&stones, 6), Some(22));
+        assert_eq!(count_stones(&stones, 25), Some(55312));
     }
 
     #[test]
     fn test_part_2_trivial() {
-        let input = application::Input::new("0");
-        let result = part_2(input).to_string().parse::<usize>().unwrap();
+        let result = count_stones(&parse_stones("0"), 75).unwrap();
         assert!(result > 0);
     }
+
+    #[test]
+    fn test_blink_stone() {
+        assert_eq!(blink_stone(0), Some((1, None)));
+        assert_eq!(blink_stone(1000), Some((10, Some(0))));
+        assert_eq!(blink_stone(253000), Some((253, Some(0))));
+        assert_eq!(blink_stone(999), Some((2021976, None)));
+        assert_eq!(blink_stone(1_000_000_000_000_000_000), None);
+    }
+
+    #[test]
+    fn test_overflow() {
+        assert_eq!(count_stones(&parse_stones("1000000000000000000"), 1), None);
+        assert_eq!(count_stones(&parse_stones("125 17"), 500), None);
+    }
+
+    #[test]
+    fn test_blink_stats() {
+        // 125 17 -> 253000 1 7 -> 253 0 2024 14168 -> 512072 1 20 24 28676032
+        assert_eq!(
+            blink_stats(&parse_stones("125 17"), 3),
+            Some(vec![(3, 3), (4, 4), (5, 5)])
+        );
+        // 0 0 -> 1 1 -> 2024 2024 -> 20 24 20 24
+        assert_eq!(
+            blink_stats(&parse_stones("0 0"), 3),
+            Some(vec![(2, 1), (2, 1), (4, 2)])
+        );
+    }
 }
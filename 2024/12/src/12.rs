@@ -1,126 +1,118 @@
 use std::collections::{HashSet, VecDeque};
 
 aoc::parts!(1, 2);
-fn part_1(input: aoc::Input) -> impl ToString {
-    let map = input
-        .raw()
+
+const DIRECTIONS: [(isize, isize); 4] = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+
+/// A connected group of same-plant cells.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Region {
+    plant: char,
+    area: usize,
+    perimeter: usize,
+    /// Number of straight fence sides, including the sides around any holes.
+    sides: usize,
+    /// Inclusive `((min_x, min_y), (max_x, max_y))` of the cells in the region.
+    bounding_box: ((usize, usize), (usize, usize)),
+}
+
+fn parse_map(input: &str) -> Vec<Vec<char>> {
+    input
         .lines()
         .map(|line| line.chars().collect::<Vec<_>>())
-        .collect::<Vec<_>>();
+        .collect::<Vec<_>>()
+}
+
+/// Every region in the map, in the order their first cell appears.
+fn find_regions(map: &[Vec<char>]) -> Vec<Region> {
     let mut visited = HashSet::new();
-    let mut total_price = 0;
+    let mut regions = Vec::new();
 
     for (y, row) in map.iter().enumerate() {
         for (x, &cell) in row.iter().enumerate() {
             if !visited.contains(&(x, y)) {
-                let (area, perimeter) =
-                    calculate_area_and_perimeter(&map, x, y, cell, &mut visited);
-                total_price += area * perimeter;
+                regions.push(explore_region(map, x, y, cell, &mut visited));
             }
         }
     }
 
-    total_price
+    regions
 }
 
-fn part_2(input: aoc::Input) -> impl ToString {
-    let map = input
-        .raw()
-        .lines()
-        .map(|line| line.chars().collect::<Vec<_>>())
-        .collect::<Vec<_>>();
-    let mut visited = HashSet::new();
-    let mut total_price = 0;
+fn part_1(input: aoc::Input) -> impl ToString {
+    find_regions(&parse_map(input.raw()))
+        .iter()
+        .map(|region| region.area * region.perimeter)
+        .sum::<usize>()
+}
 
-    for (y, row) in map.iter().enumerate() {
-        for (x, &cell) in row.iter().enumerate() {
-            if !visited.contains(&(x, y)) {
-                let (area, sides) = calculate_area_and_sides(&map, x, y, cell, &mut visited);
-                total_price += area * sides;
-            }
-        }
-    }
+fn part_2(input: aoc::Input) -> impl ToString {
+    find_regions(&parse_map(input.raw()))
+        .iter()
+        .map(|region| region.area * region.sides)
+        .sum::<usize>()
+}
 
-    total_price
+/// The plant at `(x + dx, y + dy)`, or `None` off the edge of the map.
+fn plant_at(map: &[Vec<char>], x: usize, y: usize, dx: isize, dy: isize) -> Option<char> {
+    let nx = x.checked_add_signed(dx)?;
+    let ny = y.checked_add_signed(dy)?;
+    map.get(ny)?.get(nx).copied()
 }
 
-fn calculate_area_and_perimeter(
+/// Flood-fills the region containing `(start_x, start_y)`.
+///
+/// Sides are counted as corners, since a closed fence has as many corners as straight sides.
+/// Each cell checks its four diagonals: it has an outer corner there when neither neighbour
+/// towards that diagonal is in the region, and an inner corner when both are but the diagonal
+/// cell is not. This counts hole boundaries too, so regions enclosing others are priced right.
+fn explore_region(
     map: &[Vec<char>],
     start_x: usize,
     start_y: usize,
     region_char: char,
     visited: &mut HashSet<(usize, usize)>,
-) -> (usize, usize) {
-    let directions = [(0, 1), (1, 0), (0, -1), (-1, 0)];
+) -> Region {
     let mut queue = VecDeque::new();
-    let mut area = 0;
-    let mut perimeter = 0;
+    let mut region = Region {
+        plant: region_char,
+        area: 0,
+        perimeter: 0,
+        sides: 0,
+        bounding_box: ((start_x, start_y), (start_x, start_y)),
+    };
 
     queue.push_back((start_x, start_y));
     visited.insert((start_x, start_y));
 
     while let Some((x, y)) = queue.pop_front() {
-        area += 1;
-        for &(dx, dy) in &directions {
-            let nx = x as isize + dx;
-            let ny = y as isize + dy;
-
-            if nx < 0 || ny < 0 || nx >= map[0].len() as isize || ny >= map.len() as isize {
-                perimeter += 1;
+        region.area += 1;
+        let ((min_x, min_y), (max_x, max_y)) = region.bounding_box;
+        region.bounding_box = ((min_x.min(x), min_y.min(y)), (max_x.max(x), max_y.max(y)));
+
+        let inside = |dx, dy| plant_at(map, x, y, dx, dy) == Some(region_char);
+        for &(dx, dy) in &DIRECTIONS {
+            if !inside(dx, dy) {
+                region.perimeter += 1;
                 continue;
             }
 
-            let nx = nx as usize;
-            let ny = ny as usize;
-
-            if map[ny][nx] != region_char {
-                perimeter += 1;
-            } else if !visited.contains(&(nx, ny)) {
+            let (nx, ny) = ((x as isize + dx) as usize, (y as isize + dy) as usize);
+            if !visited.contains(&(nx, ny)) {
                 visited.insert((nx, ny));
                 queue.push_back((nx, ny));
             }
         }
-    }
-
-    (area, perimeter)
-}
 
-fn calculate_area_and_sides(
-    map: &[Vec<char>],
-    start_x: usize,
-    start_y: usize,
-    region_char: char,
-    visited: &mut HashSet<(usize, usize)>,
-) -> (usize, usize) {
-    let directions = [(0, 1), (1, 0), (0, -1), (-1, 0)];
-    let mut queue = VecDeque::new();
-    let mut area = 0;
-    let mut sides = 0;
-
-    queue.push_back((start_x, start_y));
-    visited.insert((start_x, start_y));
-
-    while let Some((x, y)) = queue.pop_front() {
-        area += 1;
-        for &(dx, dy) in &directions {
-            let nx = x as isize + dx;
-            let ny = y as isize + dy;
-
-            if nx < 0 || ny < 0 || nx >= map[0].len() as isize || ny >= map.len() as isize {
-                sides += 1;
-                continue;
-            }
-
-            let nx = nx as usize;
-            let ny = ny as usize;
-
-            if map[ny][nx] != region_char {
-                sides += 1;
+        for (dx, dy) in [(1, 1), (1, -1), (-1, 1), (-1, -1)] {
+            let (horizontal, vertical) = (inside(dx, 0), inside(0, dy));
+            if (!horizontal && !vertical) || (horizontal && vertical && !inside(dx, dy)) {
+                region.sides += 1;
             }
         }
     }
 
-    (area, sides)
+    region
 }
 
 #[cfg(test)]
@@ -135,9 +127,9 @@ mod tests {
             vec!['A', 'A', 'A'],
         ];
         let mut visited = HashSet::new();
-        let (area, perimeter) = calculate_area_and_perimeter(&map, 0, 0, 'A', &mut visited);
-        assert_eq!(area, 8);
-        assert_eq!(perimeter, 12);
+        let region = explore_region(&map, 0, 0, 'A', &mut visited);
+        assert_eq!(region.area, 8);
+        assert_eq!(region.perimeter, 16);
     }
 
     #[test]
@@ -148,26 +140,87 @@ mod tests {
             vec!['A', 'A', 'A'],
         ];
         let mut visited = HashSet::new();
-        let (area, sides) = calculate_area_and_sides(&map, 0, 0, 'A', &mut visited);
-        assert_eq!(area, 8);
-        assert_eq!(sides, 12);
+        let region = explore_region(&map, 0, 0, 'A', &mut visited);
+        assert_eq!(region.area, 8);
+        assert_eq!(region.sides, 8);
     }
 
     #[test]
     fn test_simple_perimeter() {
         let map = vec![vec!['A', 'A'], vec!['A', 'A']];
         let mut visited = HashSet::new();
-        let (area, perimeter) = calculate_area_and_perimeter(&map, 0, 0, 'A', &mut visited);
-        assert_eq!(area, 4);
-        assert_eq!(perimeter, 8);
+        let region = explore_region(&map, 0, 0, 'A', &mut visited);
+        assert_eq!(region.area, 4);
+        assert_eq!(region.perimeter, 8);
     }
 
     #[test]
     fn test_simple_sides() {
         let map = vec![vec!['A', 'A'], vec!['A', 'A']];
         let mut visited = HashSet::new();
-        let (area, sides) = calculate_area_and_sides(&map, 0, 0, 'A', &mut visited);
-        assert_eq!(area, 4);
-        assert_eq!(sides, 8);
+        let region = explore_region(&map, 0, 0, 'A', &mut visited);
+        assert_eq!(region.area, 4);
+        assert_eq!(region.sides, 4);
+    }
+
+    fn price(input: &str, sides: bool) -> usize {
+        find_regions(&parse_map(input))
+            .iter()
+            .map(|region| {
+                region.area
+                    * if sides {
+                        region.sides
+                    } else {
+                        region.perimeter
+                    }
+            })
+            .sum()
+    }
+
+    #[test]
+    fn test_examples() {
+        let small = "AAAA\nBBCD\nBBCC\nEEEC";
+        assert_eq!(price(small, false), 140);
+        assert_eq!(price(small, true), 80);
+
+        let nested = "OOOOO\nOXOXO\nOOOOO\nOXOXO\nOOOOO";
+        assert_eq!(price(nested, false), 772);
+        assert_eq!(price(nested, true), 436);
+
+        let e_shape = "EEEEE\nEXXXX\nEEEEE\nEXXXX\nEEEEE";
+        assert_eq!(price(e_shape, true), 236);
+
+        let diagonal_holes = "AAAAAA\nAAABBA\nAAABBA\nABBAAA\nABBAAA\nAAAAAA";
+        assert_eq!(price(diagonal_holes, true), 368);
+
+        let large = "RRRRIICCFF\nRRRRIICCCF\nVVRRRCCFFF\nVVRCCCJFFF\nVVVVCJJCFE\nVVIVCCJJEE\nVVIIICJJEE\nMIIIIIJJEE\nMIIISIJEEE\nMMMISSJEEE";
+        assert_eq!(price(large, false), 1930);
+        assert_eq!(price(large, true), 1206);
+    }
+
+    #[test]
+    fn test_find_regions() {
+        let regions = find_regions(&parse_map("OOOOO\nOXOXO\nOOOOO"));
+        assert_eq!(regions.len(), 3);
+        assert_eq!(
+            regions[0],
+            Region {
+                plant: 'O',
+                area: 13,
+                perimeter: 24,
+                sides: 12,
+                bounding_box: ((0, 0), (4, 2)),
+            }
+        );
+        assert_eq!(
+            regions[2],
+            Region {
+                plant: 'X',
+                area: 1,
+                perimeter: 4,
+                sides: 4,
+                bounding_box: ((3, 1), (3, 1)),
+            }
+        );
     }
 }
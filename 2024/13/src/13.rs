@@ -1,156 +1,274 @@
-#[derive(Debug)]
+use std::fmt;
+
+/// Tokens per press of button A and button B in the puzzle.
+const DEFAULT_COSTS: [i64; 2] = [3, 1];
+
+#[derive(Debug, Clone, Copy)]
 struct Button {
-    x: i32,
-    y: i32,
-    cost: i32,
+    x: i64,
+    y: i64,
+    cost: i64,
 }
 
 #[derive(Debug)]
 struct Machine {
-    button_a: Button,
-    button_b: Button,
+    buttons: Vec<Button>,
     prize_x: i64,
     prize_y: i64,
 }
 
-fn parse_input(input: &str, offset: i64) -> Vec<Machine> {
+/// Number of machines whose prize can be won and the fewest tokens needed to win all of them.
+#[derive(Debug, PartialEq, Eq)]
+struct Outcome {
+    prizes_won: usize,
+    total_cost: i64,
+}
+
+impl fmt::Display for Outcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} prizes won with a total cost of {}",
+            self.prizes_won, self.total_cost
+        )
+    }
+}
+
+/// Parses the two numbers after `label:`, e.g. `Button A: X+94, Y+34` or `Prize: X=8400, Y=5400`.
+fn parse_pair(line: &str) -> (i64, i64) {
+    let (_, values) = line.split_once(':').expect("Expected a label");
+    let parts: Vec<i64> = values
+        .replace("X+", "")
+        .replace("Y+", "")
+        .replace("X=", "")
+        .replace("Y=", "")
+        .split(',')
+        .filter_map(|s| s.trim().parse().ok())
+        .collect();
+
+    if parts.len() != 2 {
+        panic!("Invalid input format: {}", line);
+    }
+    (parts[0], parts[1])
+}
+
+/// Parses machines with any number of buttons, the nth button costing `costs[n]` tokens.
+fn parse_input(input: &str, offset: i64, costs: &[i64]) -> Vec<Machine> {
     let mut machines = Vec::new();
-    let mut lines = input.lines().filter(|line| !line.trim().is_empty());
-
-    while let Some(button_a_line) = lines.next() {
-        let button_b_line = lines.next().expect("Expected Button B line");
-        let prize_line = lines.next().expect("Expected Prize line");
-
-        let button_a_parts: Vec<i32> = button_a_line
-            .replace("Button A:", "")
-            .replace("X+", "")
-            .replace("Y+", "")
-            .split(',')
-            .filter_map(|s| s.trim().parse().ok())
-            .collect();
-
-        let button_b_parts: Vec<i32> = button_b_line
-            .replace("Button B:", "")
-            .replace("X+", "")
-            .replace("Y+", "")
-            .split(',')
-            .filter_map(|s| s.trim().parse().ok())
-            .collect();
-
-        let prize_parts: Vec<i64> = prize_line
-            .replace("Prize:", "")
-            .replace("X=", "")
-            .replace("Y=", "")
-            .split(',')
-            .filter_map(|s| s.trim().parse().ok())
-            .collect();
-
-        if button_a_parts.len() != 2 || button_b_parts.len() != 2 || prize_parts.len() != 2 {
-            panic!(
-                "Invalid input format: {}, {}, {}",
-                button_a_line, button_b_line, prize_line
-            );
-        }
+    let mut buttons = Vec::new();
 
-        machines.push(Machine {
-            button_a: Button {
-                x: button_a_parts[0],
-                y: button_a_parts[1],
-                cost: 3,
-            },
-            button_b: Button {
-                x: button_b_parts[0],
-                y: button_b_parts[1],
-                cost: 1,
-            },
-            prize_x: prize_parts[0] + offset,
-            prize_y: prize_parts[1] + offset,
-        });
+    for line in input.lines().filter(|line| !line.trim().is_empty()) {
+        let (x, y) = parse_pair(line);
+        if line.starts_with("Button") {
+            let cost = *costs
+                .get(buttons.len())
+                .unwrap_or_else(|| panic!("No cost configured for button {}", buttons.len()));
+            buttons.push(Button { x, y, cost });
+        } else {
+            machines.push(Machine {
+                buttons: std::mem::take(&mut buttons),
+                prize_x: x + offset,
+                prize_y: y + offset,
+            });
+        }
     }
 
     machines
 }
 
-fn solve_machine(machine: &Machine) -> Option<i64> {
-    let ax = machine.button_a.x as i64;
-    let ay = machine.button_a.y as i64;
-    let bx = machine.button_b.x as i64;
-    let by = machine.button_b.y as i64;
-    let px = machine.prize_x;
-    let py = machine.prize_y;
-
-    let determinant = ax * by - ay * bx;
-    if determinant == 0 {
-        return None; // No solution or infinite solutions
+fn extended_gcd(a: i128, b: i128) -> (i128, i128, i128) {
+    if b == 0 {
+        (a, 1, 0)
+    } else {
+        let (g, x, y) = extended_gcd(b, a % b);
+        (g, y, x - (a / b) * y)
     }
+}
 
-    let m = (px * by - py * bx) / determinant;
-    if m * determinant != px * by - py * bx {
-        return None; // m is not an integer
+/// Cheapest non-negative `(m, n)` with `a * m + b * n == p`, for `a, b >= 0`.
+///
+/// All solutions lie on the line `m = m0 + t * b / g`, `n = n0 - t * a / g`, and the cost is
+/// linear in `t`, so the cheapest is at one end of the range of `t` keeping both counts
+/// non-negative.
+fn solve_line(a: i64, b: i64, p: i64, cost_a: i64, cost_b: i64) -> Option<(i64, i64)> {
+    match (a, b) {
+        (0, 0) => return (p == 0).then_some((0, 0)),
+        (0, _) => return (p % b == 0 && p / b >= 0).then_some((0, p / b)),
+        (_, 0) => return (p % a == 0 && p / a >= 0).then_some((p / a, 0)),
+        _ => {}
     }
 
-    let n = (py - ay * m) / by;
-    if n * by != py - ay * m {
-        return None; // n is not an integer
+    let (a, b, p) = (a as i128, b as i128, p as i128);
+    let (g, x, y) = extended_gcd(a, b);
+    if p % g != 0 {
+        return None;
     }
+    let (m0, n0) = (x * (p / g), y * (p / g));
+    let (step_m, step_n) = (b / g, a / g);
 
-    if m < 0 || n < 0 {
-        return None; // No negative presses allowed
+    let t_min = (-m0).div_euclid(step_m) + ((-m0).rem_euclid(step_m) != 0) as i128;
+    let t_max = n0.div_euclid(step_n);
+    if t_min > t_max {
+        return None;
     }
 
-    Some(3 * m + n) // Total cost
+    let slope = cost_a as i128 * step_m - cost_b as i128 * step_n;
+    let t = if slope >= 0 { t_min } else { t_max };
+    Some(((m0 + t * step_m) as i64, (n0 - t * step_n) as i64))
 }
 
-aoc::parts!(1, 2);
+/// Cheapest way to reach `(px, py)` with two buttons.
+fn solve_two(a: &Button, b: &Button, px: i64, py: i64) -> Option<i64> {
+    let determinant = a.x * b.y - a.y * b.x;
+    if determinant != 0 {
+        let m = px * b.y - py * b.x;
+        let n = a.x * py - a.y * px;
+        if m % determinant != 0 || n % determinant != 0 {
+            return None; // Not a whole number of presses
+        }
+        let (m, n) = (m / determinant, n / determinant);
+        if m < 0 || n < 0 {
+            return None; // No negative presses allowed
+        }
+        return Some(a.cost * m + b.cost * n);
+    }
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let raw_data = input.raw();
-    let machines = parse_input(&raw_data, 0);
+    // Colinear buttons: solve along whichever axis they move on, then check the other one.
+    let (m, n) = if a.x != 0 || b.x != 0 {
+        solve_line(a.x, b.x, px, a.cost, b.cost)?
+    } else {
+        solve_line(a.y, b.y, py, a.cost, b.cost)?
+    };
+    (a.x * m + b.x * n == px && a.y * m + b.y * n == py).then_some(a.cost * m + b.cost * n)
+}
 
-    let (prizes_won, total_cost) = machines.iter().fold((0, 0), |(prizes, cost), machine| {
-        if let Some(min_cost) = solve_machine(machine) {
-            (prizes + 1, cost + min_cost)
-        } else {
-            (prizes, cost)
+/// Cheapest way to reach `(px, py)` with any number of buttons.
+///
+/// The last two buttons are solved exactly; every press count of the others is tried, so this is
+/// only practical for small prizes when there are more than two buttons.
+fn solve_buttons(buttons: &[Button], px: i64, py: i64) -> Option<i64> {
+    match buttons {
+        [] => (px == 0 && py == 0).then_some(0),
+        [only] => solve_two(
+            only,
+            &Button {
+                x: 0,
+                y: 0,
+                cost: 0,
+            },
+            px,
+            py,
+        ),
+        [a, b] => solve_two(a, b, px, py),
+        [first, rest @ ..] => {
+            let limit = [(first.x, px), (first.y, py)]
+                .iter()
+                .filter(|&&(step, _)| step > 0)
+                .map(|&(step, target)| target / step)
+                .min()
+                .expect("Buttons must move towards the prize");
+            (0..=limit)
+                .filter_map(|presses| {
+                    let cost = solve_buttons(rest, px - first.x * presses, py - first.y * presses)?;
+                    Some(first.cost * presses + cost)
+                })
+                .min()
         }
-    });
+    }
+}
 
-    total_cost
+fn solve_machine(machine: &Machine) -> Option<i64> {
+    solve_buttons(&machine.buttons, machine.prize_x, machine.prize_y)
 }
 
-fn part_2(input: aoc::Input) -> impl ToString {
-    let raw_data = input.raw();
-    let machines = parse_input(&raw_data, 10000000000000);
+fn play(machines: &[Machine]) -> Outcome {
+    machines.iter().filter_map(solve_machine).fold(
+        Outcome {
+            prizes_won: 0,
+            total_cost: 0,
+        },
+        |outcome, cost| Outcome {
+            prizes_won: outcome.prizes_won + 1,
+            total_cost: outcome.total_cost + cost,
+        },
+    )
+}
 
-    let (prizes_won, total_cost) = machines.iter().fold((0, 0), |(prizes, cost), machine| {
-        if let Some(min_cost) = solve_machine(machine) {
-            (prizes + 1, cost + min_cost)
-        } else {
-            (prizes, cost)
-        }
-    });
+aoc::parts!(1, 2);
 
-    total_cost
+fn part_1(input: aoc::Input) -> impl ToString {
+    let raw_data = input.raw();
+    let machines = parse_input(raw_data, 0, &DEFAULT_COSTS);
+    play(&machines).total_cost
 }
 
+fn part_2(input: aoc::Input) -> impl ToString {
+    let raw_data = input.raw();
+    let machines = parse_input(raw_data, 10000000000000, &DEFAULT_COSTS);
+    play(&machines).total_cost
+}
+
+#[cfg(test)]
 mod tests {
     use super::*;
 
+    const EXAMPLE: &str = "Button A: X+94, Y+34
+Button B: X+22, Y+67
+Prize: X=8400, Y=5400
+
+Button A: X+26, Y+66
+Button B: X+67, Y+21
+Prize: X=12748, Y=12176
+
+Button A: X+17, Y+86
+Button B: X+84, Y+37
+Prize: X=7870, Y=6450
+
+Button A: X+69, Y+23
+Button B: X+27, Y+71
+Prize: X=18641, Y=10279";
+
     #[test]
     fn test_example_input_part_1() {
-        let input =
-            ("Button A: X+94, Y+34\nButton B: X+22, Y+67\nPrize: X=8400, Y=5400\n\n".to_string(),);
-
-        let result = part_1(input);
-        assert_eq!(result.to_string(), "2 prizes won with a total cost of 480");
+        let machines = parse_input(EXAMPLE, 0, &DEFAULT_COSTS);
+        assert_eq!(
+            play(&machines).to_string(),
+            "2 prizes won with a total cost of 480"
+        );
     }
 
     #[test]
     fn test_example_input_part_2() {
-        let input =
-            ("Button A: X+94, Y+34\nButton B: X+22, Y+67\nPrize: X=8400, Y=5400\n\n".to_string(),);
+        let machines = parse_input(EXAMPLE, 10000000000000, &DEFAULT_COSTS);
+        assert_eq!(
+            play(&machines).to_string(),
+            "2 prizes won with a total cost of 875318608908"
+        );
+    }
 
-        let result = part_2(input);
-        assert!(result.to_string().contains("prizes won"));
+    #[test]
+    fn test_colinear_buttons() {
+        // B moves twice as far as A, so with A at 3 tokens B is always the better buy.
+        let input = "Button A: X+2, Y+3\nButton B: X+4, Y+6\nPrize: X=10, Y=15";
+        let machines = parse_input(input, 0, &DEFAULT_COSTS);
+        assert_eq!(solve_machine(&machines[0]), Some(3 + 2));
+
+        // Making B expensive flips the choice to A only.
+        let machines = parse_input(input, 0, &[1, 5]);
+        assert_eq!(solve_machine(&machines[0]), Some(5));
+
+        // Colinear buttons with a prize off their line.
+        let input = "Button A: X+2, Y+3\nButton B: X+4, Y+6\nPrize: X=10, Y=14";
+        let machines = parse_input(input, 0, &DEFAULT_COSTS);
+        assert_eq!(solve_machine(&machines[0]), None);
+    }
+
+    #[test]
+    fn test_more_buttons() {
+        let input = "Button A: X+1, Y+0\nButton B: X+0, Y+1\nButton C: X+5, Y+5\nPrize: X=12, Y=11";
+        let machines = parse_input(input, 0, &[1, 1, 3]);
+        // Two presses of C, then 2 of A and 1 of B.
+        assert_eq!(solve_machine(&machines[0]), Some(2 * 3 + 2 + 1));
+        assert_eq!(machines[0].buttons.len(), 3);
     }
 }
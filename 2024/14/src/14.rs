@@ -1,49 +1,115 @@
-use std::collections::HashMap;
+aoc::parts!(1, 2);
 
-aoc::parts!(1);
+const WIDTH: i64 = 101;
+const HEIGHT: i64 = 103;
 
-fn part_1(input: aoc::Input) -> impl ToString {
-    let mut robots: Vec<(i32, i32, i32, i32)> = input
-        .raw()
+#[derive(Debug, Clone, Copy)]
+struct Robot {
+    position: (i64, i64),
+    velocity: (i64, i64),
+}
+
+impl Robot {
+    /// Position after `steps` seconds, wrapping around a `width` x `height` grid.
+    fn position_after(&self, steps: i64, width: i64, height: i64) -> (i64, i64) {
+        (
+            (self.position.0 + self.velocity.0 * steps).rem_euclid(width),
+            (self.position.1 + self.velocity.1 * steps).rem_euclid(height),
+        )
+    }
+}
+
+fn parse_robots(input: &str) -> Vec<Robot> {
+    input
         .lines()
-        .filter_map(|line| {
-            let line = line.ok()?;
+        .map(|line| {
             let (p, v) = line.split_once(" v=").unwrap();
-            let (px, py) = p.split_once(',').unwrap();
+            let (px, py) = p.trim_start_matches("p=").split_once(',').unwrap();
             let (vx, vy) = v.split_once(',').unwrap();
-            Some((
-                px[2..].parse::<i32>().unwrap(),
-                py[2..].parse::<i32>().unwrap(),
-                vx[2..].parse::<i32>().unwrap(),
-                vy[2..].parse::<i32>().unwrap(),
-            ))
+            Robot {
+                position: (px.parse().unwrap(), py.parse().unwrap()),
+                velocity: (vx.parse().unwrap(), vy.parse().unwrap()),
+            }
         })
-        .collect();
-
-    let mut grid: HashMap<(i32, i32), i32> = HashMap::new();
+        .collect()
+}
 
-    for _ in 0..100 {
-        for (_i, (px, py, vx, vy)) in robots.iter_mut().enumerate() {
-            *px = (*px + *vx + 101) % 101;
-            *py = (*py + *vy + 103) % 103;
-            *grid.entry((*px, *py)).or_insert(0) += 1;
+/// Product of the robot counts in each quadrant after `steps` seconds.
+///
+/// On an odd-sized axis the middle line belongs to no quadrant; on an even-sized one the axis
+/// splits evenly.
+fn safety_factor(robots: &[Robot], width: i64, height: i64, steps: i64) -> u64 {
+    let mut quadrants = [0u64; 4];
+    for robot in robots {
+        let (x, y) = robot.position_after(steps, width, height);
+        if (width % 2 == 1 && x == width / 2) || (height % 2 == 1 && y == height / 2) {
+            continue;
         }
+        let right = x >= width / 2;
+        let bottom = y >= height / 2;
+        quadrants[right as usize + 2 * bottom as usize] += 1;
     }
+    quadrants.iter().product()
+}
 
-    let mut quadrants = [0; 4];
-    for ((x, y), count) in grid.iter() {
-        if *x < 50 && *y < 51 {
-            quadrants[0] += count;
-        } else if *x >= 50 && *y < 51 {
-            quadrants[1] += count;
-        } else if *x < 50 && *y >= 51 {
-            quadrants[2] += count;
-        } else if *x >= 50 && *y >= 51 {
-            quadrants[3] += count;
-        }
-    }
+/// Step in `0..period` at which the robots' coordinates on one axis are least spread out.
+///
+/// Each axis repeats with its own period, so this only needs to look at one period.
+fn least_variance_step(coordinates: impl Fn(i64) -> Vec<i64>, period: i64) -> i64 {
+    (0..period)
+        .min_by(|&a, &b| variance(&coordinates(a)).total_cmp(&variance(&coordinates(b))))
+        .unwrap()
+}
+
+fn variance(values: &[i64]) -> f64 {
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<i64>() as f64 / n;
+    values
+        .iter()
+        .map(|&v| (v as f64 - mean).powi(2))
+        .sum::<f64>()
+        / n
+}
 
-    quadrants.iter().product::<i32>().to_string()
+/// Smallest non-negative `t` with `t ≡ a (mod m)` and `t ≡ b (mod n)`, for coprime `m` and `n`.
+fn crt(a: i64, m: i64, b: i64, n: i64) -> Option<i64> {
+    let inverse = (1..n).find(|&k| (m * k) % n == 1)?;
+    let k = ((b - a) * inverse).rem_euclid(n);
+    Some(a + m * k)
+}
+
+/// The first step at which the robots cluster into the Christmas tree.
+///
+/// The picture is the moment both axes are simultaneously at their least variance, so each axis
+/// is solved independently over its own period and the two are combined with the CRT.
+fn tree_step(robots: &[Robot], width: i64, height: i64) -> i64 {
+    let x_step = least_variance_step(
+        |steps| {
+            robots
+                .iter()
+                .map(|robot| robot.position_after(steps, width, height).0)
+                .collect()
+        },
+        width,
+    );
+    let y_step = least_variance_step(
+        |steps| {
+            robots
+                .iter()
+                .map(|robot| robot.position_after(steps, width, height).1)
+                .collect()
+        },
+        height,
+    );
+    crt(x_step, width, y_step, height).expect("grid dimensions must be coprime")
+}
+
+fn part_1(input: aoc::Input) -> impl ToString {
+    safety_factor(&parse_robots(input.raw()), WIDTH, HEIGHT, 100)
+}
+
+fn part_2(input: aoc::Input) -> impl ToString {
+    tree_step(&parse_robots(input.raw()), WIDTH, HEIGHT)
 }
 
 #[cfg(test)]
@@ -64,10 +130,32 @@ p=9,3 v=2,3
 p=7,3 v=-1,2
 p=2,4 v=2,-3
 p=9,5 v=-3,-3";
-        assert_eq!(part_1(input.into()), "12");
+        assert_eq!(safety_factor(&parse_robots(input), 11, 7, 100), 12);
+    }
+
+    #[test]
+    fn test_crt() {
+        assert_eq!(crt(2, 3, 3, 5), Some(8));
+        assert_eq!(crt(0, 101, 0, 103), Some(0));
+        assert_eq!(crt(1, 4, 1, 6), None);
     }
-}
 
-// fn part_2(input: aoc::Input) -> impl ToString {
-//     0
-// }
+    #[test]
+    fn test_tree_step() {
+        // Robots that all meet at (5, 3) after 40 seconds and are spread out otherwise.
+        let robots: Vec<Robot> = (0..11)
+            .map(|i| {
+                let velocity = (i, 10 - i);
+                let start = Robot {
+                    position: (5, 3),
+                    velocity: (-velocity.0, -velocity.1),
+                };
+                Robot {
+                    position: start.position_after(40, 11, 7),
+                    velocity,
+                }
+            })
+            .collect();
+        assert_eq!(tree_step(&robots, 11, 7), 40);
+    }
+}
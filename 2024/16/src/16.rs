@@ -1,9 +1,192 @@
-aoc::parts!(1);
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+aoc::parts!(1, 2);
+
+const MOVE_COST: u64 = 1;
+const TURN_COST: u64 = 1000;
+
+/// Headings in clockwise order, so turning is +1 or +3 modulo 4. The reindeer starts facing east.
+const HEADINGS: [(isize, isize); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+const EAST: usize = 0;
+
+struct Maze {
+    width: usize,
+    height: usize,
+    walls: Vec<bool>,
+    start: usize,
+    end: usize,
+}
+
+impl Maze {
+    fn parse(input: &str) -> Maze {
+        let lines: Vec<&str> = input.lines().collect();
+        let height = lines.len();
+        let width = lines[0].len();
+        let mut walls = vec![false; width * height];
+        let mut start = 0;
+        let mut end = 0;
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.bytes().enumerate() {
+                let index = y * width + x;
+                match c {
+                    b'#' => walls[index] = true,
+                    b'S' => start = index,
+                    b'E' => end = index,
+                    _ => {}
+                }
+            }
+        }
+        Maze {
+            width,
+            height,
+            walls,
+            start,
+            end,
+        }
+    }
+
+    /// The open tile one step from `tile` along `heading`, if any.
+    fn step(&self, tile: usize, heading: usize, backwards: bool) -> Option<usize> {
+        let (mut dx, mut dy) = HEADINGS[heading];
+        if backwards {
+            (dx, dy) = (-dx, -dy);
+        }
+        let x = (tile % self.width).checked_add_signed(dx)?;
+        let y = (tile / self.width).checked_add_signed(dy)?;
+        let next = y * self.width + x;
+        (x < self.width && y < self.height && !self.walls[next]).then_some(next)
+    }
+
+    /// Dijkstra over (tile, heading) states, encoded as `tile * 4 + heading`.
+    ///
+    /// With `backwards` set, moves are reversed, so the result is the cost from each state to the
+    /// given starts rather than from them.
+    fn costs(&self, starts: &[usize], backwards: bool) -> Vec<u64> {
+        dijkstra(self.walls.len() * 4, starts, |state| {
+            let (tile, heading) = (state / 4, state % 4);
+            let forward = self
+                .step(tile, heading, backwards)
+                .map(|next| (next * 4 + heading, MOVE_COST));
+            let turns = [1, 3].map(|turn| (tile * 4 + (heading + turn) % 4, TURN_COST));
+            forward.into_iter().chain(turns)
+        })
+    }
+
+    fn best_score(&self) -> u64 {
+        let from_start = self.costs(&[self.start * 4 + EAST], false);
+        (0..4)
+            .map(|heading| from_start[self.end * 4 + heading])
+            .min()
+            .unwrap()
+    }
+
+    /// Number of tiles that lie on at least one lowest-score path.
+    fn best_path_tiles(&self) -> usize {
+        let from_start = self.costs(&[self.start * 4 + EAST], false);
+        let to_end = self.costs(&[0, 1, 2, 3].map(|heading| self.end * 4 + heading), true);
+        let best = (0..4)
+            .map(|heading| from_start[self.end * 4 + heading])
+            .min()
+            .unwrap();
+        (0..self.walls.len())
+            .filter(|&tile| {
+                (0..4).any(|heading| {
+                    let state = tile * 4 + heading;
+                    from_start[state].saturating_add(to_end[state]) == best
+                })
+            })
+            .count()
+    }
+}
+
+/// Shortest distances from `starts` to every state in `0..state_count`, with `u64::MAX` for
+/// unreachable states. `neighbours` yields (state, cost) pairs for the edges leaving a state.
+fn dijkstra<F, I>(state_count: usize, starts: &[usize], neighbours: F) -> Vec<u64>
+where
+    F: Fn(usize) -> I,
+    I: IntoIterator<Item = (usize, u64)>,
+{
+    let mut dist = vec![u64::MAX; state_count];
+    let mut queue = BinaryHeap::new();
+    for &start in starts {
+        dist[start] = 0;
+        queue.push(Reverse((0, start)));
+    }
+
+    while let Some(Reverse((cost, state))) = queue.pop() {
+        if cost > dist[state] {
+            continue;
+        }
+        for (next, step_cost) in neighbours(state) {
+            let next_cost = cost + step_cost;
+            if next_cost < dist[next] {
+                dist[next] = next_cost;
+                queue.push(Reverse((next_cost, next)));
+            }
+        }
+    }
+
+    dist
+}
 
 fn part_1(input: aoc::Input) -> impl ToString {
-    0
+    Maze::parse(input.raw()).best_score()
+}
+
+fn part_2(input: aoc::Input) -> impl ToString {
+    Maze::parse(input.raw()).best_path_tiles()
 }
 
-// fn part_2(input: aoc::Input) -> impl ToString {
-//     0
-// }
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIRST_EXAMPLE: &str = "\
+###############
+#.......#....E#
+#.#.###.#.###.#
+#.....#.#...#.#
+#.###.#####.#.#
+#.#.#.......#.#
+#.#.#####.###.#
+#...........#.#
+###.#.#####.#.#
+#...#.....#.#.#
+#.#.#.###.#.#.#
+#.....#...#.#.#
+#.###.#.#.#.#.#
+#S..#.....#...#
+###############";
+
+    const SECOND_EXAMPLE: &str = "\
+#################
+#...#...#...#..E#
+#.#.#.#.#.#.#.#.#
+#.#.#.#...#...#.#
+#.#.#.#.###.#.#.#
+#...#.#.#.....#.#
+#.#.#.#.#.#####.#
+#.#...#.#.#.....#
+#.#.#####.#.###.#
+#.#.#.......#...#
+#.#.###.#####.###
+#.#.#...#.....#.#
+#.#.#.#####.###.#
+#.#.#.........#.#
+#.#.#.#########.#
+#S#.............#
+#################";
+
+    #[test]
+    fn test_best_score() {
+        assert_eq!(Maze::parse(FIRST_EXAMPLE).best_score(), 7036);
+        assert_eq!(Maze::parse(SECOND_EXAMPLE).best_score(), 11048);
+    }
+
+    #[test]
+    fn test_best_path_tiles() {
+        assert_eq!(Maze::parse(FIRST_EXAMPLE).best_path_tiles(), 45);
+        assert_eq!(Maze::parse(SECOND_EXAMPLE).best_path_tiles(), 64);
+    }
+}
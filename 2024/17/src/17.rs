@@ -2,8 +2,138 @@ aoc::parts!(1, 2);
 
 use aoc::Input;
 use regex::Regex;
+use std::collections::HashMap;
+use std::env;
 
-fn run_program_part1(program: &[u8], mut a: i64, mut b: i64, mut c: i64) -> String {
+/// Execution statistics gathered by the `_profiled` interpreters.
+#[derive(Debug, Default)]
+struct Profile {
+    /// Executions per instruction pointer.
+    counts: HashMap<usize, u64>,
+    steps: u64,
+    /// Backward jumps taken, keyed by (jump ip, target ip), i.e. the loops executed.
+    back_edges: HashMap<(usize, usize), u64>,
+    /// Observed (min, max) of registers A, B and C.
+    register_ranges: [Option<(i64, i64)>; 3],
+}
+
+impl Profile {
+    fn record(&mut self, ip: usize, next_ip: usize, registers: [i64; 3]) {
+        *self.counts.entry(ip).or_insert(0) += 1;
+        self.steps += 1;
+        if next_ip <= ip {
+            *self.back_edges.entry((ip, next_ip)).or_insert(0) += 1;
+        }
+        for (range, value) in self.register_ranges.iter_mut().zip(registers) {
+            let (min, max) = range.unwrap_or((value, value));
+            *range = Some((min.min(value), max.max(value)));
+        }
+    }
+
+    /// Loops as (first ip, jump ip, times taken), hottest first.
+    fn hot_loops(&self) -> Vec<(usize, usize, u64)> {
+        let mut loops: Vec<_> = self
+            .back_edges
+            .iter()
+            .map(|(&(from, to), &count)| (to, from, count))
+            .collect();
+        loops.sort_by(|a, b| b.2.cmp(&a.2).then(a.0.cmp(&b.0)));
+        loops
+    }
+
+    fn sorted_counts(&self) -> Vec<(usize, u64)> {
+        let mut counts: Vec<_> = self
+            .counts
+            .iter()
+            .map(|(&ip, &count)| (ip, count))
+            .collect();
+        counts.sort();
+        counts
+    }
+
+    fn to_table(&self, program: &[u8]) -> String {
+        let mut table = format!(
+            "total steps: {}\n{:>4}  {:>6} {:>7} {:>10}\n",
+            self.steps, "ip", "opcode", "operand", "count"
+        );
+        for (ip, count) in self.sorted_counts() {
+            table += &format!(
+                "{:>4}  {:>6} {:>7} {:>10}\n",
+                ip,
+                program[ip],
+                program[ip + 1],
+                count
+            );
+        }
+        for (start, end, count) in self.hot_loops() {
+            table += &format!("loop {}..={} taken {} times\n", start, end, count);
+        }
+        for (name, range) in ["A", "B", "C"].iter().zip(self.register_ranges) {
+            if let Some((min, max)) = range {
+                table += &format!("{} range: {}..={}\n", name, min, max);
+            }
+        }
+        table
+    }
+
+    fn to_json(&self) -> String {
+        let counts: Vec<String> = self
+            .sorted_counts()
+            .iter()
+            .map(|(ip, count)| format!(r#""{}":{}"#, ip, count))
+            .collect();
+        let loops: Vec<String> = self
+            .hot_loops()
+            .iter()
+            .map(|(start, end, count)| {
+                format!(r#"{{"start":{},"end":{},"count":{}}}"#, start, end, count)
+            })
+            .collect();
+        let registers: Vec<String> = ["A", "B", "C"]
+            .iter()
+            .zip(self.register_ranges)
+            .filter_map(|(name, range)| {
+                range.map(|(min, max)| format!(r#""{}":{{"min":{},"max":{}}}"#, name, min, max))
+            })
+            .collect();
+        format!(
+            r#"{{"steps":{},"counts":{{{}}},"hot_loops":[{}],"registers":{{{}}}}}"#,
+            self.steps,
+            counts.join(","),
+            loops.join(","),
+            registers.join(",")
+        )
+    }
+}
+
+/// Either of the `_profiled` interpreters.
+type ProfiledInterpreter = fn(&[u8], i64, i64, i64, Option<&mut Profile>) -> String;
+
+/// Prints a profile of a run with `interpreter` to stderr when `AOC_PROFILE` is set to `table`
+/// or `json`.
+fn report_profile(interpreter: ProfiledInterpreter, program: &[u8], a: i64, b: i64, c: i64) {
+    let Ok(format) = env::var("AOC_PROFILE") else {
+        return;
+    };
+    let mut profile = Profile::default();
+    interpreter(program, a, b, c, Some(&mut profile));
+    match format.as_str() {
+        "json" => eprintln!("{}", profile.to_json()),
+        _ => eprint!("{}", profile.to_table(program)),
+    }
+}
+
+fn run_program_part1(program: &[u8], a: i64, b: i64, c: i64) -> String {
+    run_program_part1_profiled(program, a, b, c, None)
+}
+
+fn run_program_part1_profiled(
+    program: &[u8],
+    mut a: i64,
+    mut b: i64,
+    mut c: i64,
+    mut profile: Option<&mut Profile>,
+) -> String {
     let mut ip = 0usize;
     let mut output_values = std::collections::VecDeque::new(); // Use VecDeque for part 1
 
@@ -25,17 +155,18 @@ fn run_program_part1(program: &[u8], mut a: i64, mut b: i64, mut c: i64) -> Stri
     while ip + 1 < program.len() {
         let opcode = program[ip];
         let operand = program[ip + 1];
+        let current_ip = ip;
 
         match opcode {
             0 => {
                 let denom_power = combo(operand, a, b, c);
                 let denominator = 2i64.pow(denom_power as u32);
-                a = a / denominator;
+                a /= denominator;
                 ip += 2;
             }
             1 => {
                 let val = literal(operand);
-                b = b ^ val;
+                b ^= val;
                 ip += 2;
             }
             2 => {
@@ -51,7 +182,7 @@ fn run_program_part1(program: &[u8], mut a: i64, mut b: i64, mut c: i64) -> Stri
                 }
             }
             4 => {
-                b = b ^ c;
+                b ^= c;
                 ip += 2;
             }
             5 => {
@@ -76,6 +207,9 @@ fn run_program_part1(program: &[u8], mut a: i64, mut b: i64, mut c: i64) -> Stri
             }
         }
 
+        if let Some(profile) = profile.as_deref_mut() {
+            profile.record(current_ip, ip, [a, b, c]);
+        }
         if ip >= program.len() {
             break;
         }
@@ -85,13 +219,24 @@ fn run_program_part1(program: &[u8], mut a: i64, mut b: i64, mut c: i64) -> Stri
     result.join(",")
 }
 
-fn run_program_part2(program: &[u8], mut a: i64, mut b: i64, mut c: i64) -> String {
+fn run_program_part2(program: &[u8], a: i64, b: i64, c: i64) -> String {
+    run_program_part2_profiled(program, a, b, c, None)
+}
+
+fn run_program_part2_profiled(
+    program: &[u8],
+    mut a: i64,
+    mut b: i64,
+    mut c: i64,
+    mut profile: Option<&mut Profile>,
+) -> String {
     let mut ip = 0usize;
     let mut output = String::new();
 
     while ip + 1 < program.len() {
         let opcode = program[ip];
         let operand = program[ip + 1];
+        let current_ip = ip;
 
         let operand_val = match opcode {
             0 | 6 | 7 => match operand {
@@ -120,6 +265,9 @@ fn run_program_part2(program: &[u8], mut a: i64, mut b: i64, mut c: i64) -> Stri
             3 => {
                 if a != 0 {
                     ip = operand_val as usize;
+                    if let Some(profile) = profile.as_deref_mut() {
+                        profile.record(current_ip, ip, [a, b, c]);
+                    }
                     continue;
                 }
             }
@@ -136,6 +284,9 @@ fn run_program_part2(program: &[u8], mut a: i64, mut b: i64, mut c: i64) -> Stri
         }
 
         ip += 2;
+        if let Some(profile) = profile.as_deref_mut() {
+            profile.record(current_ip, ip, [a, b, c]);
+        }
         if ip >= program.len() {
             break;
         }
@@ -164,6 +315,7 @@ fn parse_input(input_str: &str) -> (i64, i64, i64, Vec<u8>) {
 fn part_1(input: Input) -> impl ToString {
     let input_str = input.raw();
     let (a, b, c, program) = parse_input(input_str);
+    report_profile(run_program_part1_profiled, &program, a, b, c);
     run_program_part1(&program, a, b, c) // Call the original version
 }
 
@@ -203,6 +355,13 @@ fn crt_solve(equations: &[(i64, i64)]) -> i64 {
 fn part_2(input: Input) -> impl ToString {
     let input_str = input.raw();
     let (_, b, c, program) = parse_input(input_str);
+    let a = find_self_printing_a(&program, b, c);
+    report_profile(run_program_part2_profiled, &program, a, b, c);
+    a
+}
+
+/// The value of register A that makes the program print itself.
+fn find_self_printing_a(program: &[u8], b: i64, c: i64) -> i64 {
     let target = program
         .iter()
         .map(|&x| x.to_string())
@@ -221,7 +380,7 @@ fn part_2(input: Input) -> impl ToString {
     if equations.is_empty() {
         for a_candidate in 0..8 {
             let a = a_candidate;
-            let output = run_program_part2(&program, a, b, c);
+            let output = run_program_part2(program, a, b, c);
             if output == target {
                 return a;
             }
@@ -230,13 +389,13 @@ fn part_2(input: Input) -> impl ToString {
     }
     let a = crt_solve(&equations);
 
-    let output = run_program_part2(&program, a, b, c);
+    let output = run_program_part2(program, a, b, c);
     if output == target {
         a
     } else {
         //If the CRT doesn't provide a solution, we need to search from our starting point up until we find a solution.
         for test_a in (a..100000000).step_by(8) {
-            let output = run_program_part2(&program, test_a, b, c);
+            let output = run_program_part2(program, test_a, b, c);
             if output == target {
                 return test_a;
             }
@@ -264,4 +423,30 @@ fn test_part_1_examples() {
 #[test]
 fn test_part_2_example() {
     let input = "Register A: 2024\nRegister B: 0\nRegister C: 0\n\nProgram: 0,3,5,4,3,0";
+    let (_, b, c, program) = parse_input(input);
+    assert_eq!(run_program_part2(&program, 117440, b, c), "0,3,5,4,3,0");
+}
+
+#[test]
+fn test_profile() {
+    let program = [0, 1, 5, 4, 3, 0];
+    let mut first = Profile::default();
+    let mut second = Profile::default();
+    assert_eq!(
+        run_program_part1_profiled(&program, 10, 0, 0, Some(&mut first)),
+        run_program_part2_profiled(&program, 10, 0, 0, Some(&mut second))
+    );
+    for profile in [first, second] {
+        assert_eq!(profile.steps, 12);
+        assert_eq!(profile.sorted_counts(), vec![(0, 4), (2, 4), (4, 4)]);
+        assert_eq!(profile.hot_loops(), vec![(0, 4, 3)]);
+        assert_eq!(
+            profile.register_ranges,
+            [Some((0, 5)), Some((0, 0)), Some((0, 0))]
+        );
+        assert_eq!(
+            profile.to_json(),
+            r#"{"steps":12,"counts":{"0":4,"2":4,"4":4},"hot_loops":[{"start":0,"end":4,"count":3}],"registers":{"A":{"min":0,"max":5},"B":{"min":0,"max":0},"C":{"min":0,"max":0}}}"#
+        );
+    }
 }
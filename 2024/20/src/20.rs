@@ -1,35 +1,20 @@
 aoc::parts!(1, 2);
 
-use std::collections::{HashMap, HashSet, VecDeque};
+use std::collections::{HashMap, VecDeque};
+
+/// Minimum picoseconds a cheat has to save to be counted.
+const THRESHOLD: usize = 100;
 
 fn part_1(input: aoc::Input) -> impl ToString {
     let (grid, start, end) = parse_input(input.raw());
-    let shortest_path = bfs(&grid, start, end).unwrap();
-    println!("Shortest path: {}", shortest_path);
-    let cheats = find_cheats(&grid, start, end, shortest_path, 2);
-    println!("Cheats: {:?}", cheats);
-
-    let mut count = 0;
-    for (saved_time, num_cheats) in cheats.iter() {
-        if *saved_time >= 100 {
-            count += num_cheats;
-        }
-    }
-    count
+    let cheats = find_cheats(&grid, start, end, 2);
+    count_cheats_saving(&cheats, THRESHOLD)
 }
 
 fn part_2(input: aoc::Input) -> impl ToString {
     let (grid, start, end) = parse_input(input.raw());
-    let shortest_path = bfs(&grid, start, end).unwrap();
-    let cheats = find_cheats(&grid, start, end, shortest_path, 20);
-
-    let mut count = 0;
-    for (saved_time, num_cheats) in cheats.iter() {
-        if *saved_time >= 100 {
-            count += num_cheats;
-        }
-    }
-    count
+    let cheats = find_cheats(&grid, start, end, 20);
+    count_cheats_saving(&cheats, THRESHOLD)
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -54,30 +39,26 @@ fn parse_input(input: &str) -> (Vec<Vec<char>>, Point, Point) {
     (grid, start, end)
 }
 
-fn bfs(grid: &Vec<Vec<char>>, start: Point, end: Point) -> Option<usize> {
+/// Distance along the track from `from` to every cell, `None` for walls and unreachable cells.
+fn distance_map(grid: &[Vec<char>], from: Point) -> Vec<Vec<Option<usize>>> {
+    let mut distances = vec![vec![None; grid[0].len()]; grid.len()];
+    distances[from.y][from.x] = Some(0);
     let mut queue = VecDeque::new();
-    queue.push_back((start, 0));
-    let mut visited = HashSet::new();
-    visited.insert(start);
+    queue.push_back((from, 0));
 
     while let Some((current, distance)) = queue.pop_front() {
-        if current == end {
-            return Some(distance);
-        }
-
-        let neighbors = get_neighbors(grid, current, false);
-        for neighbor in neighbors {
-            if !visited.contains(&neighbor) {
-                visited.insert(neighbor);
+        for neighbor in get_neighbors(grid, current, false) {
+            if distances[neighbor.y][neighbor.x].is_none() {
+                distances[neighbor.y][neighbor.x] = Some(distance + 1);
                 queue.push_back((neighbor, distance + 1));
             }
         }
     }
 
-    None
+    distances
 }
 
-fn get_neighbors(grid: &Vec<Vec<char>>, point: Point, can_pass_through_walls: bool) -> Vec<Point> {
+fn get_neighbors(grid: &[Vec<char>], point: Point, can_pass_through_walls: bool) -> Vec<Point> {
     let mut neighbors = Vec::new();
     let directions = [(0, -1), (0, 1), (-1, 0), (1, 0)];
 
@@ -100,62 +81,45 @@ fn get_neighbors(grid: &Vec<Vec<char>>, point: Point, can_pass_through_walls: bo
     neighbors
 }
 
+/// Histogram of time saved (picoseconds) to number of distinct cheats saving exactly that much.
+///
+/// A cheat goes from one track cell to another at most `max_cheat` steps away, ignoring walls.
+/// With one distance map from the start and one to the end, each pair of track cells within
+/// Manhattan radius `max_cheat` is priced in constant time.
 fn find_cheats(
-    grid: &Vec<Vec<char>>,
+    grid: &[Vec<char>],
     start: Point,
     end: Point,
-    shortest_path: usize,
     max_cheat: usize,
 ) -> HashMap<usize, usize> {
+    let from_start = distance_map(grid, start);
+    let to_end = distance_map(grid, end);
     let mut cheats = HashMap::new();
-    let mut visited_starts = HashSet::new();
-
-    for y in 0..grid.len() {
-        for x in 0..grid[0].len() {
-            let start_point = Point { x, y };
-            println!("Checking start point: {:?}", start_point); // Debug
-            if grid[y][x] != '#' && !visited_starts.contains(&start_point) {
-                if let Some(dist_to_start) = bfs(grid, start, start_point) {
-                    visited_starts.insert(start_point);
-                    println!("  Distance to start: {}", dist_to_start); // Debug
-                    for cheat_len in 1..=max_cheat {
-                        println!("    Checking cheat length: {}", cheat_len); // Debug
-                        let mut queue = VecDeque::new();
-                        queue.push_back((start_point, 0, cheat_len));
-                        let mut visited = HashSet::new();
-                        visited.insert((start_point, cheat_len));
-                        while let Some((current, dist_from_start, remaining_cheat)) =
-                            queue.pop_front()
-                        {
-                            println!(
-                                "Current: {:?}, dist: {}, remaining: {}",
-                                current, dist_from_start, remaining_cheat
-                            ); // Debug
-                            if remaining_cheat == 0 && grid[current.y][current.x] != '#' {
-                                if let Some(dist_to_end) = bfs(grid, current, end) {
-                                    println!("        Distance to end: {}", dist_to_end); // Debug
-                                    let total_dist = dist_to_start + dist_to_end + cheat_len;
-                                    let saved_time = shortest_path as i32 - total_dist as i32;
-                                    if saved_time > 0 {
-                                        println!("          Saved time: {}", saved_time); // Debug
-                                        *cheats.entry(saved_time as usize).or_insert(0) += 1;
-                                    }
-                                }
-                            } else if remaining_cheat > 0 {
-                                let neighbors = get_neighbors(grid, current, true);
-                                for neighbor in neighbors {
-                                    let next_state = (neighbor, remaining_cheat - 1);
-                                    if !visited.contains(&next_state) {
-                                        visited.insert(next_state);
-                                        queue.push_back((
-                                            neighbor,
-                                            dist_from_start + 1,
-                                            remaining_cheat - 1,
-                                        ));
-                                    }
-                                }
-                            }
-                        }
+    let Some(shortest_path) = from_start[end.y][end.x] else {
+        return cheats;
+    };
+
+    let radius = max_cheat as isize;
+    for (y, row) in from_start.iter().enumerate() {
+        for (x, dist_to_start) in row.iter().enumerate() {
+            let Some(dist_to_start) = *dist_to_start else {
+                continue;
+            };
+            for dy in -radius..=radius {
+                let reach = radius - dy.abs();
+                for dx in -reach..=reach {
+                    let (Some(cx), Some(cy)) = (x.checked_add_signed(dx), y.checked_add_signed(dy))
+                    else {
+                        continue;
+                    };
+                    let Some(&Some(dist_to_end)) = to_end.get(cy).and_then(|row| row.get(cx))
+                    else {
+                        continue;
+                    };
+                    let total_dist =
+                        dist_to_start + dx.unsigned_abs() + dy.unsigned_abs() + dist_to_end;
+                    if total_dist < shortest_path {
+                        *cheats.entry(shortest_path - total_dist).or_insert(0) += 1;
                     }
                 }
             }
@@ -164,3 +128,64 @@ fn find_cheats(
 
     cheats
 }
+
+/// Number of cheats saving at least `threshold` picoseconds.
+fn count_cheats_saving(cheats: &HashMap<usize, usize>, threshold: usize) -> usize {
+    cheats
+        .iter()
+        .filter(|(&saved_time, _)| saved_time >= threshold)
+        .map(|(_, &num_cheats)| num_cheats)
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = "\
+###############
+#...#...#.....#
+#.#.#.#.#.###.#
+#S#...#.#.#...#
+#######.#.#.###
+#######.#.#...#
+#######.#.###.#
+###..E#...#...#
+###.#######.###
+#...###...#...#
+#.#####.#.###.#
+#.#...#.#.#...#
+#.#.#.#.#.#.###
+#...#...#...###
+###############";
+
+    #[test]
+    fn test_find_cheats() {
+        let (grid, start, end) = parse_input(EXAMPLE);
+        let cheats = find_cheats(&grid, start, end, 2);
+        let expected = [
+            (2, 14),
+            (4, 14),
+            (6, 2),
+            (8, 4),
+            (10, 2),
+            (12, 3),
+            (20, 1),
+            (36, 1),
+            (38, 1),
+            (40, 1),
+            (64, 1),
+        ];
+        assert_eq!(cheats, HashMap::from(expected));
+        assert_eq!(count_cheats_saving(&cheats, 20), 5);
+    }
+
+    #[test]
+    fn test_find_long_cheats() {
+        let (grid, start, end) = parse_input(EXAMPLE);
+        let cheats = find_cheats(&grid, start, end, 20);
+        assert_eq!(cheats[&76], 3);
+        assert_eq!(cheats[&50], 32);
+        assert_eq!(count_cheats_saving(&cheats, 50), 285);
+    }
+}
@@ -1,5 +1,7 @@
 aoc::parts!(1, 2);
 
+use std::thread;
+
 fn part_1(input: aoc::Input) -> impl ToString {
     let raw_data = input.raw();
 
@@ -7,9 +9,10 @@ fn part_1(input: aoc::Input) -> impl ToString {
     let initial_secrets: Vec<u64> = raw_data.lines().map(|line| line.parse().unwrap()).collect();
 
     // Calculate the 2000th secret number for each buyer and sum them
+    let jump = SecretJump::new();
     let sum_2000th = initial_secrets
         .iter()
-        .map(|&secret| simulate_secret(secret, 2000))
+        .map(|&secret| jump.nth_secret(secret, 2000))
         .sum::<u64>();
 
     sum_2000th
@@ -21,29 +24,8 @@ fn part_2(input: aoc::Input) -> impl ToString {
     // Parse input: each line is an initial secret number for a buyer
     let initial_secrets: Vec<u64> = raw_data.lines().map(|line| line.parse().unwrap()).collect();
 
-    // Generate all possible sequences of four price changes (-9 to 9 inclusive)
-    let mut max_bananas = 0;
-
-    for a in -9..=9 {
-        for b in -9..=9 {
-            for c in -9..=9 {
-                for d in -9..=9 {
-                    let sequence = [a, b, c, d];
-                    let total_bananas: u64 = initial_secrets
-                        .iter()
-                        .map(|&secret| {
-                            calculate_bananas_for_sequence_optimized(secret, &sequence, 2000)
-                        })
-                        .sum();
-
-                    if total_bananas > max_bananas {
-                        max_bananas = total_bananas;
-                    }
-                }
-            }
-        }
-    }
-
+    let threads = thread::available_parallelism().map_or(1, |n| n.get());
+    let (_, max_bananas) = best_sequence(&initial_secrets, 2000, threads);
     max_bananas
 }
 
@@ -65,38 +47,143 @@ fn simulate_secret(mut secret: u64, steps: usize) -> u64 {
     secret
 }
 
-fn calculate_bananas_for_sequence_optimized(secret: u64, sequence: &[i32; 4], steps: usize) -> u64 {
+/// Bits in a secret number; every step is taken modulo `2^SECRET_BITS`.
+const SECRET_BITS: usize = 24;
+
+/// A linear map on secrets over GF(2), stored as the image of each single-bit secret.
+type BitMatrix = [u64; SECRET_BITS];
+
+/// Jump-ahead secret generation.
+///
+/// Each step of `simulate_secret` only XORs shifted copies of the secret together and masks it,
+/// so it is linear over GF(2) and can be written as a 24x24 bit matrix. `powers[k]` holds that
+/// matrix raised to `2^k`, so any number of steps is a product of at most 64 of them.
+struct SecretJump {
+    powers: Vec<BitMatrix>,
+}
+
+impl SecretJump {
+    fn new() -> SecretJump {
+        let mut step = [0; SECRET_BITS];
+        for (bit, column) in step.iter_mut().enumerate() {
+            *column = simulate_secret(1 << bit, 1);
+        }
+
+        let mut powers = vec![step];
+        for _ in 1..u64::BITS {
+            let last = powers.last().unwrap();
+            powers.push(compose(last, last));
+        }
+        SecretJump { powers }
+    }
+
+    /// The secret `steps` steps after `secret`, in `O(log steps)` matrix applications.
+    fn nth_secret(&self, secret: u64, steps: u64) -> u64 {
+        self.powers
+            .iter()
+            .enumerate()
+            .filter(|&(k, _)| steps >> k & 1 == 1)
+            .fold(secret, |secret, (_, matrix)| apply(matrix, secret))
+    }
+}
+
+fn apply(matrix: &BitMatrix, secret: u64) -> u64 {
+    matrix
+        .iter()
+        .enumerate()
+        .filter(|&(bit, _)| secret >> bit & 1 == 1)
+        .fold(0, |result, (_, column)| result ^ column)
+}
+
+/// The matrix of applying `second` after `first`.
+fn compose(first: &BitMatrix, second: &BitMatrix) -> BitMatrix {
+    first.map(|column| apply(second, column))
+}
+
+/// Number of distinct sequences of four price changes, each in `-9..=9`.
+const SEQUENCE_COUNT: usize = 19 * 19 * 19 * 19;
+
+/// Index of a sequence of four price changes in `0..SEQUENCE_COUNT`, as a base-19 number.
+fn encode_sequence(sequence: &[i32; 4]) -> usize {
+    sequence
+        .iter()
+        .fold(0, |index, &change| index * 19 + (change + 9) as usize)
+}
+
+fn decode_sequence(mut index: usize) -> [i32; 4] {
+    let mut sequence = [0; 4];
+    for change in sequence.iter_mut().rev() {
+        *change = (index % 19) as i32 - 9;
+        index /= 19;
+    }
+    sequence
+}
+
+/// Adds the price this buyer would sell at for every change sequence into `totals`.
+///
+/// The monkey sells at the first occurrence of a sequence, so later occurrences are skipped
+/// using `seen`, which holds the `buyer` stamp of the last buyer to sell on each sequence.
+fn add_buyer_bananas(secret: u64, steps: usize, buyer: u32, totals: &mut [u64], seen: &mut [u32]) {
     let mut current_secret = secret;
     let mut last_price = (current_secret % 10) as i32;
     let mut changes = [0; 4];
-    let mut match_idx = 0;
 
-    for _ in 0..steps {
-        // Generate next price
+    for step in 0..steps {
         current_secret = simulate_secret(current_secret, 1);
         let price = (current_secret % 10) as i32;
-
-        // Calculate the price change
-        let change = price - last_price;
+        changes.rotate_left(1);
+        changes[3] = price - last_price;
         last_price = price;
 
-        // Update the rolling changes array
-        changes[match_idx] = change;
-        match_idx = (match_idx + 1) % 4;
-
-        // Check if the current sequence matches
-        if changes
-            .iter()
-            .cycle()
-            .skip(match_idx)
-            .take(4)
-            .eq(sequence.iter())
-        {
-            return price as u64; // Return the price if sequence matches
+        let index = encode_sequence(&changes);
+        if step >= 3 && seen[index] != buyer {
+            seen[index] = buyer;
+            totals[index] += price as u64;
         }
     }
+}
 
-    0 // No match found
+/// Total bananas for every change sequence (indexed by `encode_sequence`), summed over buyers.
+///
+/// Buyers are split across `threads` workers, each with its own accumulator, and merged at the
+/// end.
+fn banana_totals(secrets: &[u64], steps: usize, threads: usize) -> Vec<u64> {
+    let chunk_size = secrets.len().div_ceil(threads.max(1)).max(1);
+    thread::scope(|scope| {
+        let workers: Vec<_> = secrets
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut totals = vec![0; SEQUENCE_COUNT];
+                    // Stamps start at 1 so that 0 means no buyer has sold on the sequence yet.
+                    let mut seen = vec![0; SEQUENCE_COUNT];
+                    for (buyer, &secret) in chunk.iter().enumerate() {
+                        add_buyer_bananas(secret, steps, buyer as u32 + 1, &mut totals, &mut seen);
+                    }
+                    totals
+                })
+            })
+            .collect();
+
+        let mut totals = vec![0; SEQUENCE_COUNT];
+        for worker in workers {
+            for (total, bananas) in totals.iter_mut().zip(worker.join().unwrap()) {
+                *total += bananas;
+            }
+        }
+        totals
+    })
+}
+
+/// The change sequence that earns the most bananas, with that banana total.
+fn best_sequence(secrets: &[u64], steps: usize, threads: usize) -> ([i32; 4], u64) {
+    let totals = banana_totals(secrets, steps, threads);
+    let (index, &bananas) = totals
+        .iter()
+        .enumerate()
+        .max_by_key(|&(index, &bananas)| (bananas, std::cmp::Reverse(index)))
+        .unwrap();
+    (decode_sequence(index), bananas)
 }
 
 #[cfg(test)]
@@ -123,11 +210,50 @@ mod tests {
         let initial_secrets: Vec<u64> = input.lines().map(|line| line.parse().unwrap()).collect();
 
         let sequence = [-2, 1, -1, 3];
-        let bananas: u64 = initial_secrets
-            .iter()
-            .map(|&secret| calculate_bananas_for_sequence_optimized(secret, &sequence, 2000))
-            .sum();
+        let bananas = banana_totals(&initial_secrets, 2000, 1)[encode_sequence(&sequence)];
 
         assert_eq!(bananas, 23);
+        assert_eq!(best_sequence(&initial_secrets, 2000, 1), (sequence, 23));
+        assert_eq!(best_sequence(&initial_secrets, 2000, 3), (sequence, 23));
+    }
+
+    #[test]
+    fn test_sequence_encoding() {
+        for sequence in [[-9, -9, -9, -9], [-2, 1, -1, 3], [9, 9, 9, 9]] {
+            assert_eq!(decode_sequence(encode_sequence(&sequence)), sequence);
+        }
+        assert_eq!(encode_sequence(&[9, 9, 9, 9]), SEQUENCE_COUNT - 1);
+    }
+
+    #[test]
+    fn test_first_occurrence_only() {
+        // Secret 123 yields prices 0, 6, 5, 4, 4, 6, 4, 4, 2, 4 with the sequence -1,-1,0,2
+        // first selling at 6.
+        let mut totals = vec![0; SEQUENCE_COUNT];
+        let mut seen = vec![0; SEQUENCE_COUNT];
+        add_buyer_bananas(123, 9, 1, &mut totals, &mut seen);
+        assert_eq!(totals[encode_sequence(&[-1, -1, 0, 2])], 6);
+        assert_eq!(totals.iter().filter(|&&bananas| bananas > 0).count(), 6);
+    }
+
+    #[test]
+    fn test_jump_ahead() {
+        let jump = SecretJump::new();
+        for secret in [0, 1, 123, 2024, 16777215] {
+            for steps in [0, 1, 2, 10, 2000, 65537] {
+                assert_eq!(
+                    jump.nth_secret(secret, steps),
+                    simulate_secret(secret, steps as usize)
+                );
+            }
+        }
+
+        // Jumping is additive, so a huge jump can be checked against two smaller ones.
+        let far = 1_000_000_000_000_000_000;
+        assert_eq!(
+            jump.nth_secret(123, far + 2000),
+            jump.nth_secret(jump.nth_secret(123, far), 2000)
+        );
+        assert_eq!(jump.nth_secret(123, far) >> SECRET_BITS, 0);
     }
 }